@@ -1,15 +1,21 @@
 use crate::{
-    field::Pos,
+    field::{Field, Pos},
     game::{Game, Player, Status},
     piece::Piece,
     rng::RomuDuoJrRand,
+    strategy::Strategy,
 };
-use std::{collections::HashSet, time::Instant};
+use std::time::Instant;
 
 #[allow(clippy::module_name_repetitions)]
 pub struct SimpleAi {
     own_player: Player,
     rng: RomuDuoJrRand,
+    /// Print what the AI is doing, and why.
+    reasoning: bool,
+    /// The number of empty spaces the AI looks at for its piece, picked at random.
+    /// Fewer than all make it miss wins and traps, as a handicap.
+    budget: usize,
 }
 
 impl SimpleAi {
@@ -18,9 +24,45 @@ impl SimpleAi {
         Self {
             rng: RomuDuoJrRand::with_seed(seed),
             own_player,
+            reasoning: false,
+            budget: usize::MAX,
         }
     }
 
+    /// Prints what the AI is doing, and why, during the game.
+    #[must_use]
+    pub fn with_reasoning(mut self, reasoning: bool) -> Self {
+        self.reasoning = reasoning;
+        self
+    }
+
+    /// Only looks at `budget` of the empty spaces for its piece, as a handicap.
+    #[must_use]
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = budget.max(1);
+        self
+    }
+
+    /// Returns where `piece` completes a line on `field`, if it does anywhere.
+    /// This is how the AI detects wins, one move ahead.
+    pub fn find_win(field: &Field, piece: Piece) -> Option<Pos> {
+        field.empty_spaces().into_iter().find(|&pos| {
+            let mut field = field.clone();
+            field.put(pos, piece).unwrap();
+            field.wins_through(pos)
+        })
+    }
+
+    /// Returns the pieces among `pieces` that complete a line somewhere on `field`, i.e. that
+    /// let the opponent win right away, if we give them one.
+    pub fn poisoned_pieces(field: &Field, pieces: &[Piece]) -> Vec<Piece> {
+        pieces
+            .iter()
+            .filter(|&&piece| Self::find_win(field, piece).is_some())
+            .copied()
+            .collect()
+    }
+
     /// Tries to play the game iteratively, searching for a locally optimal move
     /// Strategy:
     ///     We are given a piece by the opponent, we will then calculate all states that are
@@ -43,7 +85,7 @@ impl SimpleAi {
                 starting_player: player,
             } => {
                 assert!(self.own_player == player);
-                if game.ai_reasoning {
+                if self.reasoning {
                     println!("AI: Does not matter which piece we pick on the initial move.");
                 }
                 // return a random piece from `remaining_pieces`
@@ -54,18 +96,30 @@ impl SimpleAi {
             Status::Move {
                 next_player: _,
                 next_piece: our_piece,
+                claimable,
             } => {
                 // This is where the interesting stuff happens.
 
-                let it = if game.ai_reasoning {
+                // Our opponent completed a line, but didn't call it. That's our win then.
+                if claimable {
+                    if self.reasoning {
+                        println!("AI: Quarto! Our opponent missed to call their win.");
+                    }
+                    game.claim_missed_win().unwrap();
+                    return game.clone();
+                }
+                if game.rules().misere {
+                    return self.play_misere(game, our_piece);
+                }
+
+                let it = if self.reasoning {
                     Some(Instant::now())
                 } else {
                     None
                 };
 
-                // Grab the empty spaces.
-                let empty_spaces = t_game.field.empty_spaces();
-                if game.ai_reasoning {
+                let empty_spaces = self.budget_spaces(&t_game.field);
+                if self.reasoning {
                     println!(
                         "AI: There are {} empty spaces for us to put our piece on",
                         empty_spaces.len()
@@ -83,10 +137,16 @@ impl SimpleAi {
                         .expect("Huh? AI should only do legal moves.");
 
                     // Check if any of these moves are winning.
-                    if state.field.check_field_for_win() {
+                    if state.field.wins_through(pos) {
                         // Do early return here.
-                        // next piece can be randomly chosen, as we will win this turn.
                         let mut new_game = game.clone();
+                        if game.rules().call_quarto {
+                            new_game
+                                .call_quarto(pos)
+                                .expect("Ai should only do legal moves");
+                            return new_game;
+                        }
+                        // next piece can be randomly chosen, as we will win this turn.
                         let next_piece = if game.remaining_pieces().is_empty() {
                             our_piece
                         } else {
@@ -101,111 +161,68 @@ impl SimpleAi {
                     states.push((state, pos));
                 }
 
-                if game.ai_reasoning {
+                if self.reasoning {
                     println!("AI: We have {} states for our move", states.len());
                 }
 
-                // This tracks which states we will remove after we calculate for the adversary.
-                let mut removals = Vec::new();
-
-                // This tracks the pieces, we should not pick, i.e. they allow the opponent to win.
-                let mut non_picks = HashSet::new();
-
-                // None of these states win immediately, try to check if any of the remaining
-                // pieces will let the opponent win.
-                for (state_idx, (state, _our_pos)) in states.iter().enumerate() {
-                    // This is the piece we will give to our opponent.
-                    for piece in state.remaining_pieces() {
-                        // Perform all the moves our opponent could do with this piece.
-                        for pos in state.field.empty_spaces() {
-                            // Grab a clone
-                            let mut new_state = state.clone();
-                            // Perform the move
-                            new_state
-                                .field
-                                .put(pos, *piece)
-                                .expect("huh ai should only do legal moves!");
-
-                            // Check if any of these moves are winning.
-                            if new_state.field.check_field_for_win() && game.ai_reasoning {
-                                println!("Piece: {piece:?} will let opponent win on pos {pos:?} if we place ours({our_piece:?}) on {pos:?}");
-                                // remove these states from the states vector.
-                                removals.push(state_idx);
-                                // Add the piece to the non_picks.
-                                non_picks.insert(piece);
+                // The pieces we can give our opponent after each of our placements, without
+                // letting them win right away. This decides our move, whatever we print.
+                let remaining_pieces = t_game.remaining_pieces();
+                let safe_picks: Vec<Vec<Piece>> = states
+                    .iter()
+                    .map(|(state, our_pos)| {
+                        let poisoned = Self::poisoned_pieces(&state.field, remaining_pieces);
+                        if self.reasoning {
+                            for piece in &poisoned {
+                                println!("AI: Piece {piece:?} will let our opponent win, if we place ours ({our_piece:?}) on {our_pos:?}");
                             }
                         }
-                    }
-                }
+                        // Keep the order of `remaining_pieces`, so that the same seed yields the
+                        // same game.
+                        remaining_pieces
+                            .iter()
+                            .filter(|piece| !poisoned.contains(piece))
+                            .copied()
+                            .collect()
+                    })
+                    .collect();
+                let safe_states: Vec<usize> = (0..states.len())
+                    .filter(|&idx| !safe_picks[idx].is_empty())
+                    .collect();
 
-                let remaining_pieces = game.remaining_pieces().iter().collect::<HashSet<_>>();
-                if game.ai_reasoning {
+                if self.reasoning {
                     println!("AI: Game has {} remaining pieces", remaining_pieces.len());
                     println!(
-                        "AI: We have {} pieces that we want to avoid",
-                        non_picks.len()
+                        "AI: {} of our {} states leave a piece that does not let our opponent win",
+                        safe_states.len(),
+                        states.len()
                     );
-                }
-
-                let potential_picks: Vec<Piece> = remaining_pieces
-                    .difference(&non_picks)
-                    .map(|x| **x)
-                    .collect();
-
-                if game.ai_reasoning {
                     println!("AI: calculated all states that we can put things on without our opponent immediately winning after {:.4} us", it.unwrap().elapsed().as_micros());
                 }
 
-                // This means, our opponent will definitely win next round or it is a draw :(
-                // Just shortcut and pick any piece.
-                if potential_picks.is_empty() {
-                    if game.ai_reasoning {
-                        println!("AI: Loss is imminent, just give a random piece");
-                    }
-                    if game.remaining_pieces().is_empty() {
-                        // This will be a draw.
-                        game.do_move(states[0].1, our_piece).unwrap();
-                        return game.clone();
-                    }
-                    let random_piece = *self.rng.choose(game.remaining_pieces());
-                    game.do_move(states[0].1, random_piece).unwrap();
+                // The last piece is placed, this will be a draw.
+                if remaining_pieces.is_empty() {
+                    game.do_move(states[0].1, our_piece).unwrap();
                     return game.clone();
                 }
-                //let potential_picks = Vec::from(potential_picks);
-
-                let random_potential_pick = self.rng.choose(potential_picks);
-
-                // remove the states we do not want, i.e. the next move will let our opponent win.
-                let states: Vec<(Game, Pos)> = states
-                    .iter()
-                    .enumerate()
-                    // Get all states, which do not want to remove
-                    // This value is the idx.
-                    .filter(|(idx, _)| !removals.contains(idx))
-                    // Map to the state and pos
-                    .map(|(_, state_pos)| state_pos)
-                    .cloned()
-                    .collect();
 
                 // Oh no! we cannot avoid a game loss here. Just return.
-                if states.is_empty() {
-                    if game.ai_reasoning {
+                if safe_states.is_empty() {
+                    if self.reasoning {
                         println!("AI: We will lose on the next move, wherever we place our piece and whichever piece we select! :<");
                     }
                     // return a random piece from `remaining_pieces`
-                    let random_piece = *self.rng.choose(game.remaining_pieces());
+                    let random_piece = *self.rng.choose(remaining_pieces);
 
                     let random_pos = self.rng.choose(game.field.empty_spaces());
                     game.do_move(random_pos, random_piece).unwrap();
                     return game.clone();
                 }
 
-                // Pick a random state from this list for now.
-                let state = self.rng.choose(states.iter());
-
-                // Grab the best move and then construct the new game.
-                #[allow(clippy::cast_possible_truncation)]
-                game.do_move(state.1, random_potential_pick)
+                // Pick a random state, and a random safe piece for it, for now.
+                let &idx = self.rng.choose(safe_states.iter());
+                let piece = *self.rng.choose(safe_picks[idx].iter());
+                game.do_move(states[idx].1, piece)
                     .expect("ai should only do legal moves!");
                 game.clone()
             }
@@ -216,3 +233,218 @@ impl SimpleAi {
         }
     }
 }
+
+impl SimpleAi {
+    /// Grabs the empty spaces, or, with a handicap, some of them.
+    fn budget_spaces(&mut self, field: &Field) -> Vec<Pos> {
+        let mut empty_spaces = field.empty_spaces();
+        if empty_spaces.len() > self.budget {
+            self.rng.shuffle(&mut empty_spaces);
+            empty_spaces.truncate(self.budget);
+        }
+        empty_spaces
+    }
+
+    /// Plays a move in misère Quarto, where completing a line loses.
+    /// Strategy:
+    ///     Giving a piece that completes some line does no harm here, our opponent simply places
+    ///     it elsewhere. What hurts is a piece that completes a line wherever it is placed.
+    ///     So we place our piece where it completes no line, and give the piece that leaves our
+    ///     opponent the fewest safe places, ideally none.
+    fn play_misere(&mut self, game: &mut Game, our_piece: Piece) -> Game {
+        let completes_no_line = |field: &Field, pos: Pos, piece: Piece| {
+            let mut field = field.clone();
+            field.put(pos, piece).unwrap();
+            !field.wins_through(pos)
+        };
+        let safe_spaces = |field: &Field, piece: Piece| -> Vec<Pos> {
+            field
+                .empty_spaces()
+                .into_iter()
+                .filter(|&pos| completes_no_line(field, pos, piece))
+                .collect()
+        };
+
+        // Only the spaces within our budget are considered for our own piece.
+        let empty_spaces = self.budget_spaces(&game.field);
+        let our_spaces: Vec<Pos> = empty_spaces
+            .iter()
+            .copied()
+            .filter(|&pos| completes_no_line(&game.field, pos, our_piece))
+            .collect();
+        if our_spaces.is_empty() {
+            if self.reasoning {
+                println!("AI: Wherever we place our piece, it completes a line! :<");
+            }
+            let pos = self.rng.choose(empty_spaces);
+            let piece = if game.remaining_pieces().is_empty() {
+                our_piece
+            } else {
+                *self.rng.choose(game.remaining_pieces())
+            };
+            game.do_move(pos, piece).unwrap();
+            return game.clone();
+        }
+        if game.remaining_pieces().is_empty() {
+            // The last piece, there is nothing left to give away.
+            let pos = self.rng.choose(our_spaces);
+            game.do_move(pos, our_piece).unwrap();
+            return game.clone();
+        }
+
+        // For each of our safe places and each piece, count the safe places of our opponent.
+        let mut best = Vec::new();
+        let mut fewest = usize::MAX;
+        for &pos in &our_spaces {
+            let mut field = game.field.clone();
+            field.put(pos, our_piece).unwrap();
+            for &piece in game.remaining_pieces() {
+                let count = safe_spaces(&field, piece).len();
+                if count < fewest {
+                    fewest = count;
+                    best.clear();
+                }
+                if count == fewest {
+                    best.push((pos, piece));
+                }
+            }
+        }
+        if self.reasoning {
+            println!(
+                "AI: We have {} safe places, our opponent will have {fewest}",
+                our_spaces.len()
+            );
+        }
+
+        let (pos, piece) = self.rng.choose(best);
+        game.do_move(pos, piece)
+            .expect("ai should only do legal moves!");
+        game.clone()
+    }
+}
+
+/// Plays random legal moves, the weakest possible opponent.
+#[allow(clippy::module_name_repetitions)]
+pub struct RandomAi {
+    rng: RomuDuoJrRand,
+}
+
+impl RandomAi {
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            rng: RomuDuoJrRand::with_seed(seed),
+        }
+    }
+}
+
+impl Strategy for RandomAi {
+    fn play(&mut self, game: &Game) -> Game {
+        let mut game = game.clone();
+        if game.is_initial_move() {
+            let piece = *self.rng.choose(game.remaining_pieces());
+            game.initial_move(piece).unwrap();
+        } else {
+            let pos = self.rng.choose(game.field.empty_spaces());
+            // On the last move, there is nothing left to give away.
+            let piece = if game.remaining_pieces().is_empty() {
+                game.next_piece().unwrap()
+            } else {
+                *self.rng.choose(game.remaining_pieces())
+            };
+            game.do_move(pos, piece).unwrap();
+        }
+        game
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ai::SimpleAi,
+        game::{Game, Player},
+        notation::{parse_notation, to_notation},
+        piece::Piece,
+        rules::Rules,
+        strategy::Strategy,
+    };
+
+    #[test]
+    fn test_budget() {
+        // 3 completes the top row, but with a budget of one space, the AI mostly doesn't see it.
+        let game = parse_notation("012./..../..../.... 3 1").unwrap();
+        assert_eq!(
+            SimpleAi::find_win(&game.field, Piece::with_props(3)),
+            Some((3, 0))
+        );
+        let mut wins = 0;
+        for seed in 0..20 {
+            let mut ai = SimpleAi::with_seed(Player::PlayerOne, seed);
+            assert!(!ai.play(&game).running());
+            let mut ai = SimpleAi::with_seed(Player::PlayerOne, seed).with_budget(1);
+            if !ai.play(&game).running() {
+                wins += 1;
+            }
+        }
+        assert!(wins < 10);
+    }
+
+    #[test]
+    fn test_avoids_poisoned_pieces() {
+        // After 2 on the top row, pieces that share an attribute with 0, 1 and 2 complete it.
+        let game = parse_notation("01../..../..../.... 2 1").unwrap();
+        for seed in 0..20 {
+            let mut ai = SimpleAi::with_seed(Player::PlayerOne, seed);
+            let game = ai.play(&game);
+            let next_piece = game.next_piece().unwrap();
+            assert_eq!(SimpleAi::find_win(&game.field, next_piece), None);
+        }
+    }
+
+    #[test]
+    fn test_reasoning_does_not_change_moves() {
+        for seed in 0..50 {
+            let [without, with] = [false, true].map(|reasoning| {
+                let mut game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+                let mut ais = [Player::PlayerOne, Player::PlayerTwo]
+                    .map(|player| SimpleAi::with_seed(player, seed).with_reasoning(reasoning));
+                let mut moves = vec![];
+                while game.running() {
+                    let ai = match game.player() {
+                        Player::PlayerOne => &mut ais[0],
+                        Player::PlayerTwo => &mut ais[1],
+                    };
+                    game = ai.play(&game);
+                    moves.push(to_notation(&game));
+                }
+                moves
+            });
+            assert_eq!(without, with, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn test_misere_avoids_line() {
+        // 3 completes the top row, which loses in misère.
+        let game = parse_notation("012./..../..../.... 3 1 variants=misere").unwrap();
+        for seed in 0..20 {
+            let mut ai = SimpleAi::with_seed(Player::PlayerOne, seed);
+            let game = ai.play(&game);
+            assert!(game.running());
+            assert!(game.field.get((3, 0)).is_none());
+        }
+    }
+
+    #[test]
+    fn test_misere_budget() {
+        // With a budget of one space, the AI sometimes doesn't see that 3 completes the top row.
+        let game = parse_notation("012./..../..../.... 3 1 variants=misere").unwrap();
+        let mut losses = 0;
+        for seed in 0..50 {
+            let mut ai = SimpleAi::with_seed(Player::PlayerOne, seed).with_budget(1);
+            if !ai.play(&game).running() {
+                losses += 1;
+            }
+        }
+        assert!(losses > 0);
+    }
+}
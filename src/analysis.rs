@@ -0,0 +1,198 @@
+//! A move-by-move analysis of a recorded game: how each move scores in a [`SearchAi`] search,
+//! better alternatives to the mistakes, where the game was lost, and how many pieces were
+//! poisoned along the way.
+
+use std::sync::Arc;
+
+use crate::{
+    ai::SimpleAi,
+    game::{Game, Move, Player},
+    notation::to_notation,
+    record::{move_to_notation, Record},
+    search::{SearchAi, WIN, WIN_THRESHOLD},
+    tt::TranspositionTable,
+    tutor::Verdict,
+};
+
+/// The analysis of a single move.
+#[derive(Debug, Clone)]
+pub struct MoveAnalysis {
+    pub player: Player,
+    pub mv: Move,
+    /// The score of the position after the move, from the view of player one.
+    pub eval: i16,
+    /// How the move compares to the best one, `None` for the initial move.
+    pub verdict: Option<Verdict>,
+    /// The best move and its score from the view of player one, if `mv` was worse.
+    pub best: Option<(Move, i16)>,
+    /// The number of remaining pieces that complete a line somewhere, after the move.
+    pub poisoned_pieces: usize,
+}
+
+/// The analysis of a whole game.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub moves: Vec<MoveAnalysis>,
+    /// The index of the move that turned a position that was not lost into a lost one.
+    pub lost_at: Option<usize>,
+}
+
+/// Analyzes each move of `record`, with a search `depth` moves deep.
+pub fn analyze(record: &Record, depth: u8, seed: u64) -> Analysis {
+    const TT_MB: usize = 64;
+    let mut search = SearchAi::new(
+        depth,
+        seed,
+        Arc::new(TranspositionTable::with_size_mb(TT_MB)),
+    );
+
+    let positions = record.positions();
+    let mut moves = Vec::with_capacity(record.moves.len());
+    let mut lost_at = None;
+    for (i, &mv) in record.moves.iter().enumerate() {
+        // Analyze without time pressure.
+        let mut game = positions[i].clone();
+        game.clock = None;
+        let player = game.player();
+        let view = |score: i16| match player {
+            Player::PlayerOne => score,
+            Player::PlayerTwo => -score,
+        };
+
+        let (score, verdict, best) = if game.is_initial_move() {
+            (0, None, None)
+        } else {
+            let (best_mv, best) = search.best_move(&game).expect("The game is running");
+            let score = search.evaluate_move(&game, mv);
+            let verdict = Verdict::new(score, best);
+            if lost_at.is_none() && best >= -WIN_THRESHOLD && score < -WIN_THRESHOLD {
+                lost_at = Some(i);
+            }
+            let best = (verdict != Verdict::Best).then(|| (best_mv, view(best)));
+            (score, Some(verdict), best)
+        };
+        moves.push(MoveAnalysis {
+            player,
+            mv,
+            eval: view(score),
+            verdict,
+            best,
+            poisoned_pieces: poisoned_pieces(&positions[i + 1]),
+        });
+    }
+    Analysis { moves, lost_at }
+}
+
+/// Counts the remaining pieces that complete a line somewhere on the field of `game`.
+fn poisoned_pieces(game: &Game) -> usize {
+    if !game.running() {
+        return 0;
+    }
+    SimpleAi::poisoned_pieces(&game.field, game.remaining_pieces()).len()
+}
+
+/// Writes a score from the view of player one: a number, or `+#n` for a forced win of player
+/// one in `n` moves, and `-#n` for a forced win of player two.
+fn eval_str(eval: i16) -> String {
+    if eval > WIN_THRESHOLD {
+        format!("+#{}", WIN - eval)
+    } else if eval < -WIN_THRESHOLD {
+        format!("-#{}", WIN + eval)
+    } else {
+        eval.to_string()
+    }
+}
+
+/// Prints the analysis as a table, one move per line.
+pub fn report_text(record: &Record, analysis: &Analysis) {
+    println!("Start: {}", to_notation(&record.start));
+    println!("Evaluations are from the view of player one, +#n/-#n: a forced win/loss in n moves.");
+    println!();
+    println!(
+        "{:>4}  {:<9} {:<8} {:>6} {:>8}  Verdict",
+        "Move", "Player", "Played", "Eval", "Poisoned"
+    );
+    for (i, analysis) in analysis.moves.iter().enumerate() {
+        let verdict = match (analysis.verdict, analysis.best) {
+            (Some(verdict), Some((best, eval))) => format!(
+                "{verdict} (better: {}, {})",
+                move_to_notation(best),
+                eval_str(eval)
+            ),
+            (Some(verdict), None) => verdict.to_string(),
+            (None, _) => String::new(),
+        };
+        println!(
+            "{:>4}  {:<9} {:<8} {:>6} {:>8}  {verdict}",
+            i + 1,
+            analysis.player.to_string(),
+            move_to_notation(analysis.mv),
+            eval_str(analysis.eval),
+            analysis.poisoned_pieces,
+        );
+    }
+    println!();
+    match analysis.lost_at {
+        Some(i) => println!(
+            "The game was lost with move {}, by {}.",
+            i + 1,
+            analysis.moves[i].player
+        ),
+        None => println!("Nobody made a losing move."),
+    }
+}
+
+/// Prints the analysis as JSON.
+pub fn report_json(record: &Record, analysis: &Analysis) {
+    let player_num = |player: Player| match player {
+        Player::PlayerOne => 1,
+        Player::PlayerTwo => 2,
+    };
+    println!("{{");
+    println!("  \"start\": \"{}\",", to_notation(&record.start));
+    match analysis.lost_at {
+        Some(i) => println!("  \"lost_at\": {},", i + 1),
+        None => println!("  \"lost_at\": null,"),
+    }
+    println!("  \"moves\": [");
+    for (i, analysis) in analysis.moves.iter().enumerate() {
+        let verdict = analysis
+            .verdict
+            .map_or("null".to_string(), |verdict| format!("\"{verdict}\""));
+        let best = analysis.best.map_or("null".to_string(), |(best, eval)| {
+            format!(
+                "{{\"move\": \"{}\", \"eval\": {eval}}}",
+                move_to_notation(best)
+            )
+        });
+        let separator = if i + 1 < record.moves.len() { "," } else { "" };
+        println!(
+            "    {{\"move\": {}, \"player\": {}, \"played\": \"{}\", \"eval\": {}, \"poisoned_pieces\": {}, \"verdict\": {verdict}, \"best\": {best}}}{separator}",
+            i + 1,
+            player_num(analysis.player),
+            move_to_notation(analysis.mv),
+            analysis.eval,
+            analysis.poisoned_pieces,
+        );
+    }
+    println!("  ]");
+    println!("}}");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{analysis::analyze, record::Record, rules::Rules, tutor::Verdict};
+
+    #[test]
+    fn test_analyze() {
+        // Player two gives away 0, which completes the top row, instead of blocking it with 3.
+        let record = Record::parse("e8c./..../..../.... 3 2\n3,3:0 3,0:1", Rules::CLASSIC).unwrap();
+        let analysis = analyze(&record, 2, 1);
+        assert_eq!(analysis.moves.len(), 2);
+        assert_eq!(analysis.moves[0].verdict, Some(Verdict::GaveWinningPiece));
+        assert!(analysis.moves[0].best.is_some());
+        assert_eq!(analysis.lost_at, Some(0));
+        assert_eq!(analysis.moves[1].verdict, Some(Verdict::Best));
+        assert_eq!(analysis.moves[1].poisoned_pieces, 0);
+    }
+}
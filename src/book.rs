@@ -0,0 +1,275 @@
+//! An opening book: the best move for each position of the first few plies, up to symmetry.
+//!
+//! Positions are keyed by their [`CanonicalKey`], and the book stores the canonical key of the
+//! position after the best move. That way, a move can be looked up for any position that is
+//! equivalent to one in the book, without transforming the move back.
+//!
+//! A book is built with a deep [`SearchAi`] search of each position, and written in a compact
+//! binary format:
+//!  * the magic bytes `QBK1`,
+//!  * the number of plies, and the length of the rules in bytes, as one byte each,
+//!  * the rules, see [`crate::rules`],
+//!  * the length of a key, as one byte, and the number of entries, as 4 bytes little endian,
+//!  * each entry, as the key of the position followed by the key of the position after the
+//!    best move, sorted by the first key.
+//!
+//! A book that was loaded at startup, see [`install`], is consulted by every strategy built by
+//! [`crate::strategy::StrategySpec`], and by the AI of an interactive game.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    thread,
+};
+
+use crate::{
+    game::{Game, Move, Player},
+    rng::split_seed,
+    rules::Rules,
+    search::SearchAi,
+    symmetry::CanonicalKey,
+    tt::TranspositionTable,
+};
+
+/// The first bytes of a book file.
+const MAGIC: &[u8; 4] = b"QBK1";
+
+/// The book consulted by all strategies, if one was loaded.
+static INSTALLED: OnceLock<Book> = OnceLock::new();
+
+/// The best moves of the first few plies of games with a set of rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Book {
+    rules: Rules,
+    /// Positions with fewer moves than this, including the initial move, are in the book.
+    plies: u8,
+    /// The position after the best move, for each position.
+    moves: HashMap<CanonicalKey, CanonicalKey>,
+}
+
+impl Book {
+    /// The memory used for the transposition table of the search of each position.
+    const TT_MB: usize = 4;
+
+    /// Builds a book for the first `plies` plies of games with `rules`, searching each position
+    /// `depth` moves deep, on `threads` threads. The book only depends on the `seed`, not on the
+    /// number of threads.
+    pub fn build(rules: Rules, plies: u8, depth: u8, seed: u64, threads: usize) -> Self {
+        let mut moves = HashMap::new();
+        let mut level = vec![Game::new(Player::PlayerOne, rules)];
+        for ply in 0..plies {
+            let mut visited = HashSet::new();
+            level.retain(|game| game.running() && visited.insert(game.canonical_key()));
+            // Sorted, so that the seed of each position does not depend on the order we found
+            // it in.
+            level.sort_by_cached_key(Game::canonical_key);
+
+            let next = AtomicUsize::new(0);
+            let results = Mutex::new(vec![]);
+            thread::scope(|s| {
+                for _ in 0..threads.max(1) {
+                    s.spawn(|| loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(game) = level.get(i) else {
+                            return;
+                        };
+                        // A fresh table for each position keeps the search independent of the
+                        // positions searched before.
+                        let mut search = SearchAi::new(
+                            depth,
+                            split_seed(seed, (u64::from(ply) << 32) | i as u64),
+                            Arc::new(TranspositionTable::with_size_mb(Self::TT_MB)),
+                        );
+                        let (mv, _) = search.best_move(game).expect("The game is running");
+                        let mut after = game.clone();
+                        after
+                            .play_move(mv)
+                            .expect("The search only returns legal moves");
+                        results
+                            .lock()
+                            .unwrap()
+                            .push((game.canonical_key(), after.canonical_key()));
+                    });
+                }
+            });
+            moves.extend(results.into_inner().unwrap());
+
+            if ply + 1 < plies {
+                level = level
+                    .iter()
+                    .flat_map(|game| {
+                        game.legal_moves().into_iter().map(|mv| {
+                            let mut after = game.clone();
+                            after.play_move(mv).unwrap();
+                            after
+                        })
+                    })
+                    .collect();
+            }
+        }
+        Self {
+            rules,
+            plies,
+            moves,
+        }
+    }
+
+    /// The number of positions in the book.
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    /// The rules of the games this book is for.
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Returns the book move in `game`, if the position is in the book.
+    pub fn probe(&self, game: &Game) -> Option<Move> {
+        if game.rules() != &self.rules || !game.running() || ply(game) >= usize::from(self.plies) {
+            return None;
+        }
+        let target = self.moves.get(&game.canonical_key())?;
+        game.legal_moves().into_iter().find(|&mv| {
+            let mut after = game.clone();
+            after.play_move(mv).is_ok() && after.canonical_key() == *target
+        })
+    }
+
+    /// Writes the book in its binary format, see the [module documentation](self).
+    pub fn write(&self, mut w: impl Write) -> std::io::Result<()> {
+        let rules = self.rules.to_string();
+        let mut entries: Vec<_> = self.moves.iter().collect();
+        entries.sort_unstable();
+        let key_len = entries.first().map_or(0, |(key, _)| key.as_bytes().len());
+
+        w.write_all(MAGIC)?;
+        #[allow(clippy::cast_possible_truncation)]
+        w.write_all(&[self.plies, rules.len() as u8])?;
+        w.write_all(rules.as_bytes())?;
+        #[allow(clippy::cast_possible_truncation)]
+        w.write_all(&[key_len as u8])?;
+        #[allow(clippy::cast_possible_truncation)]
+        w.write_all(&(entries.len() as u32).to_le_bytes())?;
+        for (key, after) in entries {
+            w.write_all(key.as_bytes())?;
+            w.write_all(after.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a book written by [`Book::write`].
+    pub fn read(mut r: impl Read) -> Result<Self, String> {
+        let mut read = |len: usize| {
+            let mut buf = vec![0; len];
+            r.read_exact(&mut buf)
+                .map(|()| buf)
+                .map_err(|err| format!("Truncated book: {err}"))
+        };
+        if read(MAGIC.len())? != MAGIC {
+            return Err("Not a book".to_string());
+        }
+        let [plies, rules_len] = read(2)?[..] else {
+            unreachable!()
+        };
+        let rules = String::from_utf8(read(rules_len.into())?)
+            .map_err(|_| "Invalid rules".to_string())?
+            .parse()?;
+        let key_len = usize::from(read(1)?[0]);
+        let count = u32::from_le_bytes(read(4)?.try_into().unwrap());
+
+        // The count is not trusted with an allocation, the file may be corrupt.
+        let mut moves = HashMap::new();
+        for _ in 0..count {
+            let key = CanonicalKey::from_bytes(read(key_len)?);
+            let after = CanonicalKey::from_bytes(read(key_len)?);
+            moves.insert(key, after);
+        }
+        Ok(Self {
+            rules,
+            plies,
+            moves,
+        })
+    }
+}
+
+/// Makes `book` the book consulted by all strategies. Only the first book is installed.
+pub fn install(book: Book) {
+    let _ = INSTALLED.set(book);
+}
+
+/// Plays the move of the installed book in `game`, if there is one, and returns the new state.
+pub fn play(game: &Game) -> Option<Game> {
+    let mv = INSTALLED.get()?.probe(game)?;
+    let mut game = game.clone();
+    game.play_move(mv).unwrap();
+    Some(game)
+}
+
+/// The number of moves played to reach `game` from the empty field, including the initial move.
+fn ply(game: &Game) -> usize {
+    if game.is_initial_move() {
+        0
+    } else {
+        let size = game.field.size();
+        size * size - game.field.empty_spaces().len() + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        book::Book,
+        game::{Game, Player},
+        rules::Rules,
+        strategy::StrategySpec,
+    };
+
+    #[test]
+    fn test_build_and_probe() {
+        let book = Book::build(Rules::CLASSIC, 3, 1, 1, 2);
+        assert!(book.len() > 2);
+        assert_eq!(book, Book::build(Rules::CLASSIC, 3, 1, 1, 1));
+
+        let mut game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        let mut ai = StrategySpec::Random.build(Player::PlayerOne, 3, false);
+        for _ in 0..3 {
+            let mv = book.probe(&game).expect("The first plies are in the book");
+            assert!(game.legal_moves().contains(&mv));
+            game = ai.play(&game);
+        }
+        assert_eq!(book.probe(&game), None);
+
+        let mut misere = Rules::CLASSIC;
+        misere.misere = true;
+        assert_eq!(book.probe(&Game::new(Player::PlayerOne, misere)), None);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let book = Book::build(Rules::CLASSIC, 2, 1, 1, 1);
+        let mut bytes = vec![];
+        book.write(&mut bytes).unwrap();
+        assert_eq!(&bytes[..4], b"QBK1");
+        assert_eq!(Book::read(&bytes[..]).unwrap(), book);
+        assert!(Book::read(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Book::read(&b"QBK0"[..]).is_err());
+    }
+
+    #[test]
+    fn test_read_corrupt_count() {
+        let book = Book::build(Rules::CLASSIC, 1, 1, 1, 1);
+        let mut bytes = vec![];
+        book.write(&mut bytes).unwrap();
+        // The count follows the magic bytes, plies, the rules and the key length.
+        let count = 4 + 2 + usize::from(bytes[5]) + 1;
+        bytes[count..count + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Book::read(&bytes[..])
+            .unwrap_err()
+            .starts_with("Truncated book"));
+    }
+}
@@ -0,0 +1,205 @@
+//! Chess-style game clocks: each player starts with a base time, and gets an increment after
+//! each move. A player whose time runs out loses.
+//!
+//! A time control is written as `<minutes>+<seconds>`, e.g. `5+3` for 5 minutes, plus 3 seconds
+//! per move. A clock is written as `<time control>/<seconds left for player one>/<seconds left
+//! for player two>`, e.g. `5+3/287.5/300`.
+
+use core::{fmt::Display, str::FromStr};
+use std::time::Duration;
+
+use crate::game::Player;
+
+/// The base time and increment of a [`Clock`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl FromStr for TimeControl {
+    type Err = ();
+
+    /// Parses `<minutes>[+<seconds>]`, see the [module documentation](self).
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let (base, increment) = s.split_once('+').unwrap_or((s, "0"));
+        let base = parse_duration(base, 60.)?;
+        if base.is_zero() {
+            return Err(());
+        }
+        Ok(Self {
+            base,
+            increment: parse_duration(increment, 1.)?,
+        })
+    }
+}
+
+impl Display for TimeControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}+{}",
+            duration_value(self.base, 60.),
+            duration_value(self.increment, 1.)
+        )
+    }
+}
+
+/// The clocks of both players.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Clock {
+    pub control: TimeControl,
+    remaining: [Duration; 2],
+}
+
+impl Clock {
+    /// Starts both clocks with the base time of `control`.
+    pub fn new(control: TimeControl) -> Self {
+        Self {
+            control,
+            remaining: [control.base; 2],
+        }
+    }
+
+    /// The time `player` has left.
+    pub fn remaining(&self, player: Player) -> Duration {
+        self.remaining[index(player)]
+    }
+
+    /// Returns true if `player` ran out of time.
+    pub fn flagged(&self, player: Player) -> bool {
+        self.remaining(player).is_zero()
+    }
+
+    /// Sets the time `player` has left.
+    pub fn set_remaining(&mut self, player: Player, left: Duration) {
+        self.remaining[index(player)] = left;
+    }
+
+    /// Charges `player` for a move that took `elapsed`, and adds the increment.
+    /// Fails if the time ran out during the move, the player has no time left then.
+    pub fn charge(&mut self, player: Player, elapsed: Duration) -> Result<(), ()> {
+        let remaining = &mut self.remaining[index(player)];
+        match remaining.checked_sub(elapsed) {
+            Some(left) if !left.is_zero() => {
+                *remaining = left + self.control.increment;
+                Ok(())
+            }
+            _ => {
+                *remaining = Duration::ZERO;
+                Err(())
+            }
+        }
+    }
+
+    /// How long `player` can think about their next move, if they have to make about `moves`
+    /// more moves: an equal share of the time left, plus the increment, but never more than
+    /// half of the time left.
+    pub fn budget(&self, player: Player, moves: usize) -> Duration {
+        let remaining = self.remaining(player);
+        let moves = u32::try_from(moves.max(1)).unwrap_or(u32::MAX);
+        (remaining / moves + self.control.increment).min(remaining / 2)
+    }
+
+    /// Prints the time left of `player` as `minutes:seconds.tenths`, e.g. `4:47.5`.
+    pub fn pp_remaining(&self, player: Player) -> String {
+        let tenths = self.remaining(player).as_millis() / 100;
+        format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+    }
+}
+
+impl FromStr for Clock {
+    type Err = ();
+
+    /// Parses a clock, see the [module documentation](self).
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let [control, one, two] = s.split('/').collect::<Vec<_>>()[..] else {
+            return Err(());
+        };
+        Ok(Self {
+            control: control.parse()?,
+            remaining: [parse_duration(one, 1.)?, parse_duration(two, 1.)?],
+        })
+    }
+}
+
+impl Display for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}",
+            self.control,
+            duration_value(self.remaining[0], 1.),
+            duration_value(self.remaining[1], 1.)
+        )
+    }
+}
+
+fn index(player: Player) -> usize {
+    match player {
+        Player::PlayerOne => 0,
+        Player::PlayerTwo => 1,
+    }
+}
+
+/// Parses a non-negative number of `unit` seconds, to the millisecond.
+pub fn parse_duration(s: &str, unit: f64) -> Result<Duration, ()> {
+    let value: f64 = s.parse().map_err(|_| ())?;
+    if !value.is_finite() || value < 0. {
+        return Err(());
+    }
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok(Duration::from_millis((value * unit * 1000.).round() as u64))
+}
+
+/// The number of `unit` seconds in `duration`, to the millisecond.
+#[allow(clippy::cast_precision_loss)]
+pub fn duration_value(duration: Duration, unit: f64) -> f64 {
+    duration.as_millis() as f64 / 1000. / unit
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        clock::{Clock, TimeControl},
+        game::Player,
+    };
+
+    #[test]
+    fn test_parse() {
+        let control: TimeControl = "5+3".parse().unwrap();
+        assert_eq!(control.base, Duration::from_mins(5));
+        assert_eq!(control.increment, Duration::from_secs(3));
+        assert_eq!(control.to_string(), "5+3");
+        assert_eq!("0.5".parse::<TimeControl>().unwrap().to_string(), "0.5+0");
+        assert!("0+3".parse::<TimeControl>().is_err());
+        assert!("-1+3".parse::<TimeControl>().is_err());
+
+        let clock: Clock = "5+3/287.512/300".parse().unwrap();
+        assert_eq!(
+            clock.remaining(Player::PlayerOne),
+            Duration::from_millis(287_512)
+        );
+        assert_eq!(clock.to_string(), "5+3/287.512/300");
+        assert!("5+3/287.512".parse::<Clock>().is_err());
+    }
+
+    #[test]
+    fn test_charge() {
+        let mut clock = Clock::new("1+2".parse().unwrap());
+        clock
+            .charge(Player::PlayerOne, Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(clock.remaining(Player::PlayerOne), Duration::from_secs(52));
+        assert_eq!(clock.remaining(Player::PlayerTwo), Duration::from_mins(1));
+        assert_eq!(clock.pp_remaining(Player::PlayerOne), "0:52.0");
+
+        assert!(clock
+            .charge(Player::PlayerTwo, Duration::from_mins(1))
+            .is_err());
+        assert!(clock.flagged(Player::PlayerTwo));
+        assert!(!clock.flagged(Player::PlayerOne));
+    }
+}
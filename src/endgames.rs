@@ -0,0 +1,464 @@
+//! An endgame table: the exact outcome of every position with `K` or fewer empty squares, up to
+//! symmetry.
+//!
+//! Where the reachable positions with `K` empty squares are few enough to enumerate, e.g. on
+//! small fields, the table is complete: it solves all of them, and every position after them.
+//! On the classic field, there are far too many. Then the table only solves the positions that
+//! are reachable from the endgames of sampled games between [`SimpleAi`]s, once they are down to
+//! `K` empty squares, and other endgames are left to the search. Each position is solved
+//! exhaustively, and stored with its outcome for the player on the move, and the number of moves
+//! until the game ends with perfect play.
+//!
+//! Endgame tables are written in an indexed binary format:
+//!  * the magic bytes `QEG1`,
+//!  * the number of empty squares `K`, 1 if the table is complete or else 0, and the length of
+//!    the rules in bytes, as one byte each,
+//!  * the rules, see [`crate::rules`],
+//!  * the length of a key, as one byte, and the number of entries, as 4 bytes little endian,
+//!  * each entry, as the [`CanonicalKey`] of the position followed by one byte: the outcome
+//!    (0 for a loss, 1 for a draw, 2 for a win) in the upper two bits, and the distance in the
+//!    lower six. Entries are sorted by key, so they can be found with a binary search.
+//!
+//! An endgame table that was loaded at startup, see [`install`], is consulted by every strategy
+//! built by [`crate::strategy::StrategySpec`], and by the AI of an interactive game.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Write},
+    sync::OnceLock,
+};
+
+use crate::{
+    ai::SimpleAi,
+    game::{Game, Move, Player, Status},
+    rng::{split_seed, RomuDuoJrRand},
+    rules::Rules,
+    search::WIN,
+    strategy::Strategy,
+    symmetry::CanonicalKey,
+};
+
+/// The first bytes of an endgame table file.
+const MAGIC: &[u8; 4] = b"QEG1";
+
+/// The most moves that are played from the positions of a single ply, to enumerate all
+/// endgames. With more, the endgames are sampled instead.
+const ENUMERATION_LIMIT: usize = 20_000;
+
+/// The endgame table consulted by all strategies, if one was loaded.
+static INSTALLED: OnceLock<EndgameTable> = OnceLock::new();
+
+/// How a game ends with perfect play, for the player on the move.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Loss,
+    Draw,
+    Win,
+}
+
+/// The solution of a position.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub outcome: Outcome,
+    /// The number of moves until the game ends, including the last one.
+    pub distance: u8,
+}
+
+impl Entry {
+    /// The score of the entry, as a [`crate::search::SearchAi`] that searches to the end of the
+    /// game would find it.
+    pub fn score(self) -> i16 {
+        match self.outcome {
+            Outcome::Win => WIN - i16::from(self.distance),
+            Outcome::Draw => 0,
+            Outcome::Loss => -(WIN - i16::from(self.distance)),
+        }
+    }
+
+    /// The entry of the position before, from the view of the player that moved into this one.
+    fn before(self) -> Self {
+        Self {
+            outcome: match self.outcome {
+                Outcome::Loss => Outcome::Win,
+                Outcome::Draw => Outcome::Draw,
+                Outcome::Win => Outcome::Loss,
+            },
+            distance: self.distance + 1,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        let outcome = match self.outcome {
+            Outcome::Loss => 0,
+            Outcome::Draw => 1,
+            Outcome::Win => 2,
+        };
+        outcome << 6 | self.distance
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, String> {
+        let outcome = match byte >> 6 {
+            0 => Outcome::Loss,
+            1 => Outcome::Draw,
+            2 => Outcome::Win,
+            _ => return Err(format!("Invalid entry: {byte}")),
+        };
+        Ok(Self {
+            outcome,
+            distance: byte & 0x3f,
+        })
+    }
+}
+
+/// The solved endgames of games with a set of rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndgameTable {
+    rules: Rules,
+    /// Positions with at most this many empty squares are solved.
+    empty: u8,
+    /// Whether all reachable positions are solved, or only those of sampled games.
+    complete: bool,
+    /// The solved positions, sorted by key.
+    entries: Vec<(CanonicalKey, Entry)>,
+}
+
+impl EndgameTable {
+    /// Builds an endgame table of the positions with up to `empty` empty squares.
+    /// If they are few enough to enumerate, all reachable positions are solved. Otherwise, only
+    /// those reachable from the endgames of `games` games between [`SimpleAi`]s, played with
+    /// seeds derived from `seed`.
+    pub fn build(rules: Rules, empty: u8, games: usize, seed: u64) -> Self {
+        let (complete, endgames) = match enumerate_endgames(rules, empty) {
+            Some(endgames) => (true, endgames),
+            None => (
+                false,
+                (0..games)
+                    .map(|i| sample_endgame(rules, empty, split_seed(seed, i as u64)))
+                    .filter(Game::running)
+                    .collect(),
+            ),
+        };
+        let mut solved = HashMap::new();
+        for game in &endgames {
+            solve(game, &mut solved);
+        }
+
+        let mut entries: Vec<_> = solved.into_iter().collect();
+        entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        Self {
+            rules,
+            empty,
+            complete,
+            entries,
+        }
+    }
+
+    /// The number of solved positions.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if all reachable positions with up to `K` empty squares are solved, or false
+    /// if only those of sampled games are.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The rules of the games this endgame table is for.
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Returns the solution of `game`, if the position is in the table.
+    pub fn probe(&self, game: &Game) -> Option<Entry> {
+        if game.rules() != &self.rules
+            || !game.running()
+            || game.is_initial_move()
+            || game.field.empty_spaces().len() > self.empty.into()
+        {
+            return None;
+        }
+        let key = game.canonical_key();
+        let i = self
+            .entries
+            .binary_search_by(|(probe, _)| probe.cmp(&key))
+            .ok()?;
+        Some(self.entries[i].1)
+    }
+
+    /// Returns the best move in `game`, and the solution of the position, if the position and
+    /// all positions after it are in the table.
+    /// The best move wins as fast as possible, or loses as late as possible.
+    pub fn best_move(&self, game: &Game) -> Option<(Move, Entry)> {
+        self.probe(game)?;
+        let mut best: Option<(Move, Entry)> = None;
+        for mv in game.legal_moves() {
+            let entry = entry_after(game, mv, &mut |child| self.probe(child))?;
+            if best.is_none_or(|(_, best)| entry.score() > best.score()) {
+                best = Some((mv, entry));
+            }
+        }
+        best
+    }
+
+    /// Writes the endgame table in its binary format, see the [module documentation](self).
+    pub fn write(&self, mut w: impl Write) -> std::io::Result<()> {
+        let rules = self.rules.to_string();
+        let key_len = self
+            .entries
+            .first()
+            .map_or(0, |(key, _)| key.as_bytes().len());
+
+        w.write_all(MAGIC)?;
+        #[allow(clippy::cast_possible_truncation)]
+        w.write_all(&[self.empty, u8::from(self.complete), rules.len() as u8])?;
+        w.write_all(rules.as_bytes())?;
+        #[allow(clippy::cast_possible_truncation)]
+        w.write_all(&[key_len as u8])?;
+        #[allow(clippy::cast_possible_truncation)]
+        w.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        for (key, entry) in &self.entries {
+            w.write_all(key.as_bytes())?;
+            w.write_all(&[entry.to_byte()])?;
+        }
+        Ok(())
+    }
+
+    /// Reads an endgame table written by [`EndgameTable::write`].
+    pub fn read(mut r: impl Read) -> Result<Self, String> {
+        let mut read = |len: usize| {
+            let mut buf = vec![0; len];
+            r.read_exact(&mut buf)
+                .map(|()| buf)
+                .map_err(|err| format!("Truncated endgame table: {err}"))
+        };
+        if read(MAGIC.len())? != MAGIC {
+            return Err("Not an endgame table".to_string());
+        }
+        let [empty, complete, rules_len] = read(3)?[..] else {
+            unreachable!()
+        };
+        let complete = match complete {
+            0 => false,
+            1 => true,
+            _ => return Err(format!("Invalid completeness: {complete}")),
+        };
+        let rules = String::from_utf8(read(rules_len.into())?)
+            .map_err(|_| "Invalid rules".to_string())?
+            .parse()?;
+        let key_len = usize::from(read(1)?[0]);
+        let count = u32::from_le_bytes(read(4)?.try_into().unwrap());
+
+        // A corrupt count must end in an error rather than a huge allocation.
+        let mut entries = vec![];
+        for _ in 0..count {
+            let mut record = read(key_len + 1)?;
+            let entry = Entry::from_byte(record.pop().unwrap())?;
+            entries.push((CanonicalKey::from_bytes(record), entry));
+        }
+        if !entries.is_sorted_by(|(a, _), (b, _)| a < b) {
+            return Err("The entries are not sorted".to_string());
+        }
+        Ok(Self {
+            rules,
+            empty,
+            complete,
+            entries,
+        })
+    }
+}
+
+/// Returns all running positions with `empty` empty squares that are reachable with `rules`, up
+/// to symmetry, or those right after the initial move, if the field has no more squares.
+/// Returns `None` if there are too many to enumerate, see [`ENUMERATION_LIMIT`].
+fn enumerate_endgames(rules: Rules, empty: u8) -> Option<Vec<Game>> {
+    let mut level = vec![Game::new(Player::PlayerOne, rules)];
+    // All positions of a level have the same number of empty squares.
+    while level.first().is_some_and(|game| {
+        game.is_initial_move() || game.field.empty_spaces().len() > empty.into()
+    }) {
+        let moves: Vec<(&Game, Move)> = level
+            .iter()
+            .flat_map(|game| game.legal_moves().into_iter().map(move |mv| (game, mv)))
+            .collect();
+        if moves.len() > ENUMERATION_LIMIT {
+            return None;
+        }
+        let mut visited = HashSet::new();
+        level = moves
+            .into_iter()
+            .map(|(game, mv)| {
+                let mut child = game.clone();
+                child.play_move(mv).expect("Only legal moves are generated");
+                child
+            })
+            .filter(|child| child.running() && visited.insert(child.canonical_key()))
+            .collect();
+    }
+    Some(level)
+}
+
+/// Plays a game between [`SimpleAi`]s until there are at most `empty` empty squares left, or
+/// the game is over.
+fn sample_endgame(rules: Rules, empty: u8, seed: u64) -> Game {
+    let mut rng = RomuDuoJrRand::with_seed(seed);
+    let mut ais = [
+        SimpleAi::with_seed(Player::PlayerOne, rng.next()),
+        SimpleAi::with_seed(Player::PlayerTwo, rng.next()),
+    ];
+    let mut game = Game::new(Player::PlayerOne, rules);
+    while game.running()
+        && (game.is_initial_move() || game.field.empty_spaces().len() > empty.into())
+    {
+        let ai = match game.player() {
+            Player::PlayerOne => &mut ais[0],
+            Player::PlayerTwo => &mut ais[1],
+        };
+        game = ai.play(&game);
+    }
+    game
+}
+
+/// Solves `game` and all positions after it, and remembers them in `solved`.
+fn solve(game: &Game, solved: &mut HashMap<CanonicalKey, Entry>) -> Entry {
+    let key = game.canonical_key();
+    if let Some(&entry) = solved.get(&key) {
+        return entry;
+    }
+    let entry = game
+        .legal_moves()
+        .into_iter()
+        .map(|mv| entry_after(game, mv, &mut |child| Some(solve(child, solved))).unwrap())
+        .max_by_key(|entry| entry.score())
+        .expect("A running game has moves");
+    solved.insert(key, entry);
+    entry
+}
+
+/// Returns the solution of playing `mv` in `game`, from the view of the player on the move,
+/// using `lookup` to solve the position after the move, if the game goes on.
+fn entry_after(
+    game: &Game,
+    mv: Move,
+    lookup: &mut impl FnMut(&Game) -> Option<Entry>,
+) -> Option<Entry> {
+    let mut child = game.clone();
+    child.play_move(mv).unwrap();
+    // Depending on the rules, the game can also be won by the player that did not move.
+    match child.status {
+        Status::Won { winner } => Some(Entry {
+            outcome: if winner == game.player() {
+                Outcome::Win
+            } else {
+                Outcome::Loss
+            },
+            distance: 1,
+        }),
+        Status::Draw { .. } => Some(Entry {
+            outcome: Outcome::Draw,
+            distance: 1,
+        }),
+        _ => lookup(&child).map(Entry::before),
+    }
+}
+
+/// Makes `table` the endgame table consulted by all strategies. Only the first one is
+/// installed.
+pub fn install(table: EndgameTable) {
+    let _ = INSTALLED.set(table);
+}
+
+/// Plays the best move of the installed endgame table in `game`, if the position is in it, and
+/// returns the new state.
+pub fn play(game: &Game) -> Option<Game> {
+    let (mv, _) = INSTALLED.get()?.best_move(game)?;
+    let mut game = game.clone();
+    game.play_move(mv).unwrap();
+    Some(game)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        ai::RandomAi,
+        endgames::{sample_endgame, EndgameTable, Outcome},
+        game::{Game, Player},
+        rng::split_seed,
+        rules::Rules,
+        search::SearchAi,
+        strategy::Strategy,
+        tt::TranspositionTable,
+    };
+
+    #[test]
+    fn test_matches_search() {
+        let table = EndgameTable::build(Rules::CLASSIC, 5, 20, 1);
+        assert!(!table.is_complete());
+        assert!(table.len() > 10);
+
+        let mut search = SearchAi::new(6, 1, Arc::new(TranspositionTable::with_size_mb(1)));
+        let mut ai = RandomAi::with_seed(2);
+        let mut probed = 0;
+        for i in 0..20 {
+            let mut game = sample_endgame(Rules::CLASSIC, 5, split_seed(1, i));
+            while game.running() {
+                if let Some((mv, entry)) = table.best_move(&game) {
+                    probed += 1;
+                    assert_eq!(table.probe(&game), Some(entry));
+                    let (_, score) = search.best_move(&game).unwrap();
+                    assert_eq!(entry.score(), score, "{game:?}");
+                    assert_eq!(search.evaluate_move(&game, mv), score);
+                }
+                game = ai.play(&game);
+            }
+        }
+        assert!(probed > 0);
+    }
+
+    #[test]
+    fn test_complete() {
+        // On the 3x3 field, all positions after the initial move can be enumerated.
+        let rules = Rules {
+            size: 3,
+            ..Rules::CLASSIC
+        };
+        let table = EndgameTable::build(rules, 9, 0, 1);
+        assert!(table.is_complete());
+
+        let mut search = SearchAi::new(9, 1, Arc::new(TranspositionTable::with_size_mb(1)));
+        for seed in 0..5 {
+            let mut ai = RandomAi::with_seed(seed);
+            let mut game = ai.play(&Game::new(Player::PlayerOne, rules));
+            while game.running() {
+                let (_, entry) = table.best_move(&game).unwrap();
+                let (_, score) = search.best_move(&game).unwrap();
+                assert_eq!(entry.score(), score, "{game:?}");
+                game = ai.play(&game);
+            }
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut misere = Rules::CLASSIC;
+        misere.misere = true;
+        let table = EndgameTable::build(misere, 3, 5, 1);
+        assert!(table
+            .entries
+            .iter()
+            .any(|(_, entry)| entry.outcome == Outcome::Win));
+        let mut bytes = vec![];
+        table.write(&mut bytes).unwrap();
+        assert_eq!(&bytes[..4], b"QEG1");
+        assert_eq!(EndgameTable::read(&bytes[..]).unwrap(), table);
+        assert!(EndgameTable::read(&bytes[..bytes.len() - 1]).is_err());
+
+        let count = 4 + 3 + usize::from(bytes[6]) + 1;
+        bytes[count..count + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(EndgameTable::read(&bytes[..])
+            .unwrap_err()
+            .starts_with("Truncated endgame table"));
+    }
+}
@@ -0,0 +1,307 @@
+//! The static evaluation of positions, at the leaves of a [`crate::search::SearchAi`] search.
+//!
+//! The [`WeightedEvaluator`] scores a few features of a position, each with its own weight. The
+//! weights are written as a config file of `<name> = <value>` lines, where `#` starts a comment:
+//! ```text
+//! # Per open line, by how many attributes its pieces share: 1, 2, ...
+//! shared = 1 2 3 4 0 0
+//! safe_pieces = 3
+//! odd_safe = 0
+//! threat = -2
+//! ```
+//! Missing weights are 0.
+
+use core::{fmt::Display, str::FromStr};
+use std::sync::OnceLock;
+
+use crate::{game::Game, piece::Piece};
+
+/// The weights loaded at startup, if any.
+static INSTALLED: OnceLock<Weights> = OnceLock::new();
+
+/// Evaluations stay below this, far from the scores of wins and losses.
+pub const MAX_EVAL: i16 = 500;
+
+/// Scores positions that are not decided yet.
+pub trait Evaluator: std::fmt::Debug + Send + Sync {
+    /// Returns the score of `game`, from the view of the player on the move, that has to place
+    /// the piece in hand next. The score is within `-MAX_EVAL..=MAX_EVAL`.
+    fn evaluate(&self, game: &Game) -> i16;
+}
+
+/// The features of a position, that the [`WeightedEvaluator`] scores.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Features {
+    /// The lines with at least two pieces and an empty square, by how many attributes their
+    /// pieces share: 1, 2, ...
+    pub shared: [i16; Piece::MAX_ATTRIBUTES],
+    /// The remaining pieces, besides the one in hand, that complete no line.
+    pub safe_pieces: i16,
+    /// 1 if the number of safe pieces is odd, or else 0.
+    pub odd_safe: i16,
+    /// The lines that lack one piece, by each attribute their pieces share.
+    pub threats: [i16; Piece::MAX_ATTRIBUTES],
+}
+
+impl Features {
+    /// Extracts the features of the position in `game`.
+    pub fn new(game: &Game) -> Self {
+        let field = &game.field;
+        let attributes = field.size();
+        let mut features = Self::default();
+
+        // The properties shared by the pieces of each line one piece short of completion.
+        let mut completable = vec![];
+        for line in field.lines() {
+            let pieces: Vec<Piece> = line.iter().filter_map(|&pos| field.get(pos)).collect();
+            if pieces.len() < 2 || pieces.len() == line.len() {
+                continue;
+            }
+            let common = pieces
+                .iter()
+                .fold(u16::MAX, |common, piece| common & piece.properties);
+            let shared = common.count_ones() as usize;
+            if shared == 0 {
+                continue;
+            }
+            features.shared[shared - 1] += 1;
+            if pieces.len() + 1 == line.len() {
+                completable.push(common);
+                for (attribute, threats) in features.threats[..attributes].iter_mut().enumerate() {
+                    let mask = 1 << attribute | 1 << (attribute + attributes);
+                    if common & mask != 0 {
+                        *threats += 1;
+                    }
+                }
+            }
+        }
+
+        let safe_pieces = game
+            .remaining_pieces()
+            .iter()
+            .filter(|piece| {
+                completable
+                    .iter()
+                    .all(|&common| common & piece.properties == 0)
+            })
+            .count();
+        features.safe_pieces = i16::try_from(safe_pieces).unwrap_or(i16::MAX);
+        features.odd_safe = features.safe_pieces % 2;
+        features
+    }
+
+    /// The score of these features with `weights`.
+    pub fn score(&self, weights: &Weights) -> i16 {
+        let dot = |features: &[i16], weights: &[i16]| -> i32 {
+            features
+                .iter()
+                .zip(weights)
+                .map(|(&feature, &weight)| i32::from(feature) * i32::from(weight))
+                .sum()
+        };
+        let score = dot(&self.shared, &weights.shared)
+            + i32::from(self.safe_pieces) * i32::from(weights.safe_pieces)
+            + i32::from(self.odd_safe) * i32::from(weights.odd_safe)
+            + self
+                .threats
+                .iter()
+                .map(|&threats| i32::from(threats))
+                .sum::<i32>()
+                * i32::from(weights.threat);
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            score.clamp(-i32::from(MAX_EVAL), i32::from(MAX_EVAL)) as i16
+        }
+    }
+
+    /// Prints each feature, its weight and its contribution to the score, and the total score.
+    pub fn pp(&self, weights: &Weights, attributes: usize) {
+        let row = |name: String, value: i16, weight: i16| {
+            println!(
+                "{name:<28} {value:>5} {weight:>7} {:>6}",
+                i32::from(value) * i32::from(weight)
+            );
+        };
+        println!(
+            "{:<28} {:>5} {:>7} {:>6}",
+            "Feature", "Value", "Weight", "Score"
+        );
+        for (shared, (&value, &weight)) in self.shared[..attributes]
+            .iter()
+            .zip(&weights.shared)
+            .enumerate()
+        {
+            row(format!("open lines sharing {}", shared + 1), value, weight);
+        }
+        row(
+            "safe pieces".to_string(),
+            self.safe_pieces,
+            weights.safe_pieces,
+        );
+        row(
+            "odd number of safe pieces".to_string(),
+            self.odd_safe,
+            weights.odd_safe,
+        );
+        for (attribute, &value) in self.threats[..attributes].iter().enumerate() {
+            row(
+                format!("threats on attribute {}", attribute + 1),
+                value,
+                weights.threat,
+            );
+        }
+        println!("Total: {}", self.score(weights));
+    }
+}
+
+/// The weights of the [`Features`], see the [module documentation](self).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Weights {
+    /// Per open line, by how many attributes its pieces share: 1, 2, ...
+    pub shared: [i16; Piece::MAX_ATTRIBUTES],
+    /// Per safe piece.
+    pub safe_pieces: i16,
+    /// If the number of safe pieces is odd.
+    pub odd_safe: i16,
+    /// Per attribute shared by a line that lacks one piece.
+    pub threat: i16,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            shared: [0; Piece::MAX_ATTRIBUTES],
+            safe_pieces: 3,
+            odd_safe: 0,
+            threat: -2,
+        }
+    }
+}
+
+impl Weights {
+    /// The weights loaded at startup, see [`install`], or else the default ones.
+    pub fn installed() -> Self {
+        INSTALLED.get().copied().unwrap_or_default()
+    }
+}
+
+impl FromStr for Weights {
+    type Err = String;
+
+    /// Parses a config file, see the [module documentation](self).
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut weights = Self {
+            shared: [0; Piece::MAX_ATTRIBUTES],
+            safe_pieces: 0,
+            odd_safe: 0,
+            threat: 0,
+        };
+        for line in s.lines() {
+            let line = line.split_once('#').map_or(line, |(line, _)| line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("Expected <name> = <value>: {line}"))?;
+            let values: Vec<i16> = value
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("Invalid value: {line}"))?;
+            let weight = match name.trim() {
+                "shared" => {
+                    if values.len() > Piece::MAX_ATTRIBUTES {
+                        return Err(format!("Too many values: {line}"));
+                    }
+                    weights.shared[..values.len()].copy_from_slice(&values);
+                    continue;
+                }
+                "safe_pieces" => &mut weights.safe_pieces,
+                "odd_safe" => &mut weights.odd_safe,
+                "threat" => &mut weights.threat,
+                name => return Err(format!("Unknown weight: {name}")),
+            };
+            let [value] = values[..] else {
+                return Err(format!("Expected a single value: {line}"));
+            };
+            *weight = value;
+        }
+        Ok(weights)
+    }
+}
+
+impl Display for Weights {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let shared: Vec<String> = self.shared.iter().map(i16::to_string).collect();
+        writeln!(f, "shared = {}", shared.join(" "))?;
+        writeln!(f, "safe_pieces = {}", self.safe_pieces)?;
+        writeln!(f, "odd_safe = {}", self.odd_safe)?;
+        writeln!(f, "threat = {}", self.threat)
+    }
+}
+
+/// Makes `weights` the weights of all [`WeightedEvaluator`]s that are created without any.
+pub fn install(weights: Weights) {
+    let _ = INSTALLED.set(weights);
+}
+
+/// Scores the [`Features`] of a position with a set of [`Weights`].
+#[derive(Debug, Clone)]
+pub struct WeightedEvaluator {
+    weights: Weights,
+}
+
+impl WeightedEvaluator {
+    pub fn new(weights: Weights) -> Self {
+        Self { weights }
+    }
+}
+
+impl Default for WeightedEvaluator {
+    /// An evaluator with the [installed](Weights::installed) weights.
+    fn default() -> Self {
+        Self::new(Weights::installed())
+    }
+}
+
+impl Evaluator for WeightedEvaluator {
+    fn evaluate(&self, game: &Game) -> i16 {
+        Features::new(game).score(&self.weights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        eval::{Features, Weights},
+        notation::parse_notation,
+    };
+
+    #[test]
+    fn test_features() {
+        // Pieces with property 8, or without property 1, complete the top row.
+        let game = parse_notation("e8c./..../..../.... 3 2").unwrap();
+        let features = Features::new(&game);
+        assert_eq!(features.shared[..4], [0, 1, 0, 0]);
+        assert_eq!(features.threats[..4], [1, 0, 0, 1]);
+        // Only 1, 5 and 7 of the remaining pieces have property 1, and not 8.
+        assert_eq!(features.safe_pieces, 3);
+        assert_eq!(features.odd_safe, 1);
+
+        let weights: Weights = "shared = 0 5\nsafe_pieces = 2 # per piece\nthreat = -1"
+            .parse()
+            .unwrap();
+        assert_eq!(features.score(&weights), 5 + 3 * 2 - 2);
+    }
+
+    #[test]
+    fn test_parse_weights() {
+        let weights = Weights::default();
+        assert_eq!(weights.to_string().parse(), Ok(weights));
+        assert!("safe_pieces = 1 2".parse::<Weights>().is_err());
+        assert!("safety = 1".parse::<Weights>().is_err());
+        assert!("shared = 1 2 3 4 5 6 7".parse::<Weights>().is_err());
+        assert!("threat".parse::<Weights>().is_err());
+    }
+}
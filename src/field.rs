@@ -1,15 +1,30 @@
-use crate::{game::ArrayBase, piece::Piece};
-
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+use crate::{
+    game::ArrayBase,
+    piece::Piece,
+    rules::{Rules, WinLines},
+    symmetry::Symmetry,
+    zobrist,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::struct_field_names)]
 pub struct Field {
-    /// The field of a quarto game.
-    field: [[Option<Piece>; Self::SIZE]; Self::SIZE],
-    /// If true, squares are counted as winning condition.
-    pub square_mode: bool,
+    /// The field of a quarto game. Only the top left `size` x `size` squares are used.
+    field: [[Option<Piece>; Self::MAX_SIZE]; Self::MAX_SIZE],
+    /// The width and height of the field, and the number of attributes of the pieces.
+    size: usize,
+    /// The kinds of lines that win.
+    pub win_lines: WinLines,
+    /// The zobrist hash of all pieces on the field, see [`crate::zobrist`].
+    hash: u64,
 }
 
 pub type Pos = (usize, usize);
 
+/// Terminal escape codes to highlight the winning line, and to reset the highlighting.
+const HIGHLIGHT: &str = "\x1b[43m";
+const RESET: &str = "\x1b[0m";
+
 /// Tries to parse a "x,y" str to Pos
 pub fn try_parse_pos(s: &str) -> Result<Pos, ()> {
     let parts: Vec<&str> = s.trim().split(',').collect();
@@ -22,87 +37,140 @@ pub fn try_parse_pos(s: &str) -> Result<Pos, ()> {
 }
 
 impl Field {
-    pub const SIZE: usize = 4;
+    /// The size of the classic game.
+    pub const DEFAULT_SIZE: usize = 4;
+    pub const MIN_SIZE: usize = 3;
+    /// Each attribute of the pieces needs a row, so the size is limited by their number.
+    pub const MAX_SIZE: usize = Piece::MAX_ATTRIBUTES;
 
+    /// An empty field for the classic rules.
+    #[cfg(test)]
     pub fn new() -> Self {
-        Self::default()
+        Self::with_rules(&Rules::default())
+    }
+
+    /// An empty field of the size and with the win lines of `rules`, to be played with pieces of
+    /// `rules.size` attributes.
+    pub fn with_rules(rules: &Rules) -> Self {
+        Self::with_lines(rules.size, rules.lines)
+    }
+
+    /// An empty `size` x `size` field, on which the `win_lines` win.
+    pub fn with_lines(size: usize, win_lines: WinLines) -> Self {
+        assert!(
+            (Self::MIN_SIZE..=Self::MAX_SIZE).contains(&size),
+            "unsupported field size {size}"
+        );
+        Self {
+            field: [[None; Self::MAX_SIZE]; Self::MAX_SIZE],
+            size,
+            win_lines,
+            hash: 0,
+        }
+    }
+
+    /// The width and height of the field.
+    pub fn size(&self) -> usize {
+        self.size
     }
 
     pub fn put(&mut self, pos: Pos, piece: Piece) -> Result<(), ()> {
+        if pos.0 >= self.size || pos.1 >= self.size || piece.attributes() != self.size {
+            return Err(());
+        }
         if self.field[pos.1][pos.0].is_none() {
             self.field[pos.1][pos.0] = Some(piece);
+            self.hash ^= zobrist::placed(pos, piece);
             return Ok(());
         }
         Err(())
     }
 
-    #[cfg(test)]
     pub fn get(&self, pos: Pos) -> Option<Piece> {
         self.field[pos.1][pos.0]
     }
 
+    /// The zobrist hash of the pieces on this field, updated on each change.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
     /// Clear at a position, returning the current piece at this point
     #[cfg(test)]
     pub fn clear(&mut self, pos: Pos) -> Option<Piece> {
         let ret = self.get(pos);
+        if let Some(piece) = ret {
+            self.hash ^= zobrist::placed(pos, piece);
+        }
         self.field[pos.1][pos.0] = None;
         ret
     }
 
-    /// Checks if the win condition on this field is fulfilled.
-    pub fn check_field_for_win(&self) -> bool {
-        for row in &self.field {
-            if Self::check_array_for_win(row) {
-                return true;
-            }
-        }
+    /// Returns all lines of positions that win if they share a property, see [`WinLines`].
+    /// Squares stay 2x2 on larger fields.
+    pub fn lines(&self) -> Vec<Vec<Pos>> {
+        let size = self.size;
+        let mut lines = Vec::with_capacity(2 * size + 2 + size * size);
 
-        for column_idx in 0..Self::SIZE {
-            let col: Vec<Option<Piece>> = self.field.iter().map(|x| x[column_idx]).collect();
-            if Self::check_array_for_win(&col) {
-                return true;
+        if self.win_lines.rows {
+            for y in 0..size {
+                lines.push((0..size).map(|x| (x, y)).collect());
+            }
+            for x in 0..size {
+                lines.push((0..size).map(|y| (x, y)).collect());
             }
         }
-
-        let diagonal: Vec<Option<Piece>> = (0..Self::SIZE).map(|x| self.field[x][x]).collect();
-        if Self::check_array_for_win(&diagonal) {
-            return true;
+        if self.win_lines.diagonals {
+            lines.push((0..size).map(|i| (i, i)).collect());
+            lines.push((0..size).map(|i| ((size - 1) - i, i)).collect());
         }
-
-        let diagonal: Vec<Option<Piece>> = (0..Self::SIZE)
-            .map(|x| self.field[x][(Self::SIZE - 1) - x])
-            .collect();
-        if Self::check_array_for_win(&diagonal) {
-            return true;
+        if self.win_lines.broken_diagonals {
+            for offset in 1..size {
+                lines.push((0..size).map(|i| ((i + offset) % size, i)).collect());
+                lines.push(
+                    (0..size)
+                        .map(|i| ((size - 1 - i + offset) % size, i))
+                        .collect(),
+                );
+            }
         }
 
-        if self.square_mode {
-            for i in 0..(Self::SIZE - 1) {
-                let mut flattened_square = [None; 4];
-                for k in 0..(Self::SIZE - 1) {
-                    //for l in 0..2 {
-                    //flattened_square[l] = self.field[i][k + l]
-                    //}
-                    flattened_square[..2].copy_from_slice(&self.field[i][k..(2 + k)]);
-                    //for l in 0..2 {
-                    //flattened_square[l + 2] = self.field[i + 1][k + l]
-                    //}
-                    flattened_square[2..(2 + 2)].copy_from_slice(&self.field[i + 1][k..(2 + k)]);
-                    if Self::check_array_for_win(&flattened_square) {
-                        return true;
-                    }
+        for y in 0..size {
+            for x in 0..size {
+                let wraps = x == size - 1 || y == size - 1;
+                if (wraps && self.win_lines.wrapping_squares) || (!wraps && self.win_lines.squares)
+                {
+                    let (x1, y1) = ((x + 1) % size, (y + 1) % size);
+                    lines.push(vec![(x, y), (x1, y), (x, y1), (x1, y1)]);
                 }
             }
         }
 
-        false
+        lines
     }
 
-    // Associated helper function to determine if a given line of pieces fulfills a win condition
-    fn check_array_for_win(ary: &[Option<Piece>]) -> bool {
-        assert!(ary.len() == 4);
+    /// Checks if the win condition on this field is fulfilled.
+    pub fn check_field_for_win(&self) -> bool {
+        self.winning_line().is_some()
+    }
 
-        let mut ret = std::u8::MAX;
+    /// Checks if a line through `pos` wins, e.g. after a piece was put there.
+    pub fn wins_through(&self, pos: Pos) -> bool {
+        self.lines().iter().any(|line| {
+            line.contains(&pos) && Self::check_array_for_win(line.iter().map(|&pos| self.get(pos)))
+        })
+    }
+
+    /// Returns the first line on which all pieces share a property, if there is one.
+    pub fn winning_line(&self) -> Option<Vec<Pos>> {
+        self.lines()
+            .into_iter()
+            .find(|line| Self::check_array_for_win(line.iter().map(|&pos| self.get(pos))))
+    }
+
+    // Associated helper function to determine if a given line of pieces fulfills a win condition
+    fn check_array_for_win(ary: impl IntoIterator<Item = Option<Piece>>) -> bool {
+        let mut ret = u16::MAX;
 
         for piece in ary {
             if let Some(piece) = piece {
@@ -115,11 +183,27 @@ impl Field {
         ret != 0
     }
 
+    /// Returns this field, transformed to the representative of all fields that are equivalent
+    /// under the symmetries of the board and the pieces.
+    /// See [`crate::symmetry`] for the symmetries that are taken into account, and
+    /// [`crate::game::Game::canonical_key`] for a key that includes the piece to place next.
+    #[must_use]
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn canonical(&self) -> Self {
+        Symmetry::canonicalizing(self)[0].apply_field(self)
+    }
+
+    /// Iterates over all positions on the field, row by row.
+    pub fn all_positions(&self) -> impl Iterator<Item = Pos> {
+        let size = self.size;
+        (0..size).flat_map(move |y| (0..size).map(move |x| (x, y)))
+    }
+
     pub fn empty_spaces(&self) -> Vec<Pos> {
-        let mut ret = Vec::with_capacity(16);
+        let mut ret = Vec::with_capacity(self.size * self.size);
 
-        for x in 0..Self::SIZE {
-            for y in 0..Self::SIZE {
+        for x in 0..self.size {
+            for y in 0..self.size {
                 if self.field[y][x].is_none() {
                     ret.push((x, y));
                 }
@@ -129,39 +213,43 @@ impl Field {
         ret
     }
 
-    /// Render the field in multiple lines
+    /// Render the field in multiple lines, highlighting the winning line
     pub fn pp(&self, array_base: ArrayBase) {
-        for (y, row) in self.field.iter().enumerate() {
-            for (x, val) in (row).iter().enumerate() {
-                if x == 0 {
-                    if y > 0 {
-                        println!();
-                        println!("  > ---------- + ---------- + ---------- + ---------- <");
-                    } else {
-                        if array_base == ArrayBase::Zero {
-                            println!("        0            1            2            3       ");
-                        } else {
-                            println!("        1            2            3            4       ");
-                        }
-                        println!("  . ---------- . ---------- . ---------- . ---------- .");
-                    }
-                    let based_y = array_base.based(y);
-                    print!("{based_y} | ");
-                } else if x < Self::SIZE {
+        // Each attribute is rendered two columns wide, plus the brackets.
+        let width = 2 * self.size + 2;
+        let winning_line = self.winning_line().unwrap_or_default();
+        let dashes = vec!["-".repeat(width); self.size];
+
+        print!("    ");
+        for x in 0..self.size {
+            print!("{:^width$}   ", array_base.based(x));
+        }
+        println!();
+        println!("  . {} .", dashes.join(" . "));
+        for y in 0..self.size {
+            if y > 0 {
+                println!("  > {} <", dashes.join(" + "));
+            }
+            print!("{} | ", array_base.based(y));
+            for x in 0..self.size {
+                if x > 0 {
                     print!(" | ");
                 }
-                if let Some(val) = val {
-                    val.pp();
+                if let Some(val) = self.field[y][x] {
+                    if winning_line.contains(&(x, y)) {
+                        print!("{HIGHLIGHT}");
+                        val.pp();
+                        print!("{RESET}");
+                    } else {
+                        val.pp();
+                    }
                 } else {
-                    print!("          ");
-                }
-                if x == Self::SIZE - 1 {
-                    print!(" |");
+                    print!("{:width$}", "");
                 }
             }
+            println!(" |");
         }
-        println!();
-        println!("  ^ ---------- ^ ---------- ^ ---------- ^ ---------- ^");
+        println!("  ^ {} ^", dashes.join(" ^ "));
     }
 }
 
@@ -170,6 +258,11 @@ mod tests {
     use crate::{
         field::Field,
         piece::{Piece, Property},
+        rules::Rules,
+    };
+    const FIVE: Rules = Rules {
+        size: 5,
+        ..Rules::CLASSIC
     };
     const TEST_LIGHT_TALL: Piece = Piece::with_props(Property::Tall as u8 | Property::Light as u8);
     const TEST_DARK_SHORT: Piece = Piece::with_props(0);
@@ -179,7 +272,7 @@ mod tests {
     #[test]
     fn test_squares() {
         let mut field = Field::new();
-        field.square_mode = true;
+        field.win_lines.squares = true;
 
         field.put((0, 0), TEST_LIGHT_TALL).unwrap();
         field.put((0, 1), TEST_LIGHT_TALL).unwrap();
@@ -195,7 +288,7 @@ mod tests {
     #[test]
     fn test_wrong_prop_square() {
         let mut field = Field::new();
-        field.square_mode = true;
+        field.win_lines.squares = true;
 
         field.put((0, 0), TEST_LIGHT_TALL).unwrap();
         field.put((0, 1), TEST_DARK_SHORT).unwrap();
@@ -208,7 +301,7 @@ mod tests {
     #[test]
     fn test_different_square() {
         let mut field = Field::new();
-        field.square_mode = true;
+        field.win_lines.squares = true;
 
         field.put((2, 2), TEST_LIGHT_TALL).unwrap();
         field.put((2, 3), TEST_LIGHT_TALL).unwrap();
@@ -218,6 +311,48 @@ mod tests {
         assert!(field.check_field_for_win());
     }
 
+    #[test]
+    fn test_wrapping_square() {
+        let mut field = Field::new();
+
+        field.put((3, 3), TEST_LIGHT_TALL).unwrap();
+        field.put((0, 3), TEST_LIGHT_TALL).unwrap();
+        field.put((3, 0), TEST_LIGHT_TALL).unwrap();
+        field.put((0, 0), TEST_LIGHT_TALL).unwrap();
+
+        assert!(!field.check_field_for_win());
+        field.win_lines.squares = true;
+        assert!(!field.check_field_for_win());
+        field.win_lines.wrapping_squares = true;
+        assert!(field.check_field_for_win());
+    }
+
+    #[test]
+    fn test_broken_diagonal() {
+        let mut field = Field::new();
+
+        field.put((1, 0), TEST_LIGHT_TALL).unwrap();
+        field.put((2, 1), TEST_LIGHT_TALL).unwrap();
+        field.put((3, 2), TEST_LIGHT_TALL).unwrap();
+        field.put((0, 3), TEST_LIGHT_TALL).unwrap();
+
+        assert!(!field.check_field_for_win());
+        field.win_lines.broken_diagonals = true;
+        assert_eq!(
+            field.winning_line(),
+            Some(vec![(1, 0), (2, 1), (3, 2), (0, 3)])
+        );
+
+        let mut field = Field::new();
+        field.win_lines.broken_diagonals = true;
+        field.put((1, 0), TEST_LIGHT_TALL).unwrap();
+        field.put((0, 1), TEST_LIGHT_TALL).unwrap();
+        field.put((3, 2), TEST_LIGHT_TALL).unwrap();
+        assert!(!field.check_field_for_win());
+        field.put((2, 3), TEST_LIGHT_TALL).unwrap();
+        assert!(field.check_field_for_win());
+    }
+
     #[test]
     fn test_row() {
         let mut field = Field::new();
@@ -337,4 +472,37 @@ mod tests {
 
         assert!(field.check_field_for_win());
     }
+
+    #[test]
+    fn test_larger_field() {
+        let mut field = Field::with_rules(&FIVE);
+        assert_eq!(field.lines().len(), 12);
+        field.win_lines.squares = true;
+        assert_eq!(field.lines().len(), 28);
+        field.win_lines.wrapping_squares = true;
+        assert_eq!(field.lines().len(), 37);
+
+        assert!(field.put((0, 0), TEST_LIGHT_TALL).is_err());
+        assert!(field.put((5, 0), Piece::with_attributes(0, 5)).is_err());
+
+        for x in 0..4 {
+            field
+                .put((x, 0), Piece::with_attributes(1 << x, 5))
+                .unwrap();
+        }
+        assert!(!field.check_field_for_win());
+        field
+            .put((4, 0), Piece::with_attributes(0b1_0000, 5))
+            .unwrap();
+        assert!(!field.check_field_for_win());
+
+        // All five lack the fifth attribute.
+        let mut field = Field::with_rules(&FIVE);
+        for y in 0..5 {
+            field
+                .put((2, y), Piece::with_attributes((1 << y) & 0b1111, 5))
+                .unwrap();
+        }
+        assert!(field.check_field_for_win());
+    }
 }
@@ -1,8 +1,13 @@
 use core::fmt::Display;
+use std::time::Duration;
 
 use crate::{
+    clock::Clock,
     field::{Field, Pos},
     piece::Piece,
+    rules::{DrawRule, Rules},
+    symmetry::{self, CanonicalKey},
+    zobrist,
 };
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -37,6 +42,9 @@ pub enum Status {
     Move {
         next_player: Player,
         next_piece: Piece,
+        /// The previous player completed a line without calling "Quarto!", so the next player
+        /// can claim the win instead of placing the piece, see [`Rules::call_quarto`].
+        claimable: bool,
     },
     Won {
         winner: Player,
@@ -46,6 +54,23 @@ pub enum Status {
     },
 }
 
+/// A single move of a player.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Move {
+    /// Picks the first piece for the opponent, see [`Game::initial_move`].
+    Initial { next_piece: Piece },
+    /// Puts the piece to place on `pos`, and gives `next_piece` to the opponent.
+    /// On the last move, there is nothing left to give, and `next_piece` is the placed piece.
+    /// See [`Game::do_move`].
+    Place { pos: Pos, next_piece: Piece },
+    /// Puts the piece to place on `pos`, completing a line, and calls "Quarto!".
+    /// Only with [`Rules::call_quarto`], see [`Game::call_quarto`].
+    Quarto { pos: Pos },
+    /// Claims the win that the previous player missed to call.
+    /// Only with [`Rules::call_quarto`], see [`Game::claim_missed_win`].
+    Claim,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ArrayBase {
     Zero,
@@ -81,30 +106,120 @@ pub struct Game {
     pub field: Field,
     remaining_pieces: Vec<Piece>,
     pub status: Status,
-    pub ai_reasoning: bool,
-    pub seed: Option<u64>,
-    pub pvp: bool,
+    rules: Rules,
+    /// The clocks of the players, if the game is played with a time control.
+    pub clock: Option<Clock>,
 }
 
 impl Game {
-    /// Starts a new game
-    pub fn new(starting_player: Player) -> Self {
-        let remaining_pieces = (0..16).map(Piece::with_props).collect();
+    /// Starts a new game, played with the `2^size` pieces of `size` attributes of the `rules`.
+    pub fn new(starting_player: Player, rules: Rules) -> Self {
+        let size = rules.size;
+        #[allow(clippy::cast_possible_truncation)]
+        let remaining_pieces = (0..1u16 << size)
+            .map(|props| Piece::with_attributes(props as u8, size))
+            .collect();
 
-        #[allow(clippy::cast_precision_loss)]
         Self {
             array_base: ArrayBase::One,
             remaining_pieces,
-            field: Field::new(),
+            field: Field::with_rules(&rules),
             status: Status::InitialMove { starting_player },
-            ai_reasoning: false,
-            seed: None,
-            pvp: false,
+            rules,
+            clock: None,
+        }
+    }
+
+    /// Sets up a game in an arbitrary position.
+    /// The remaining pieces are all pieces that are neither on the `field`, nor the `next_piece`.
+    /// `player` is the player to move, or, if the game is over, the winner, or the player that
+    /// moved last for a draw. With [`Rules::call_quarto`], lines on the field only win if the
+    /// game is over, and the player to move can claim a missed win if `claimable`.
+    /// A player without time left on the `clock` lost, whatever the position on the field.
+    /// Fails if a piece is used twice, if the position can't be reached in a game, or if the
+    /// `field` was not set up for the `rules`.
+    pub fn from_position(
+        rules: Rules,
+        field: Field,
+        next_piece: Option<Piece>,
+        player: Player,
+        claimable: bool,
+        clock: Option<Clock>,
+    ) -> Result<Self, ()> {
+        if field.size() != rules.size || field.win_lines != rules.lines {
+            return Err(());
         }
+        let has_line = field.check_field_for_win();
+        if claimable && !(rules.call_quarto && has_line && next_piece.is_some()) {
+            return Err(());
+        }
+        let mut game = Self::new(player, rules);
+        let mut used: Vec<Piece> = field
+            .all_positions()
+            .filter_map(|pos| field.get(pos))
+            .chain(next_piece)
+            .collect();
+        if used.iter().any(|piece| piece.attributes() != field.size()) {
+            return Err(());
+        }
+        let count = used.len();
+        used.sort_unstable();
+        used.dedup();
+        if used.len() != count {
+            return Err(());
+        }
+        game.remaining_pieces.retain(|piece| !used.contains(piece));
+
+        let flagged: Vec<Player> = [Player::PlayerOne, Player::PlayerTwo]
+            .into_iter()
+            .filter(|&player| clock.is_some_and(|clock| clock.flagged(player)))
+            .collect();
+        game.status = if let [loser] = flagged[..] {
+            Status::Won {
+                winner: loser.next(),
+            }
+        } else if !flagged.is_empty() {
+            return Err(());
+        } else if has_line && !(rules.call_quarto && next_piece.is_some()) {
+            Status::Won { winner: player }
+        } else if let Some(next_piece) = next_piece {
+            Status::Move {
+                next_player: player,
+                next_piece,
+                claimable,
+            }
+        } else if field.empty_spaces().len() == field.size() * field.size() {
+            Status::InitialMove {
+                starting_player: player,
+            }
+        } else if game.remaining_pieces.is_empty() || field.empty_spaces().is_empty() {
+            if rules.draw == DrawRule::Draw {
+                Status::Draw {
+                    last_player: player,
+                }
+            } else {
+                Status::Won { winner: player }
+            }
+        } else {
+            return Err(());
+        };
+        game.field = field;
+        game.clock = clock;
+        Ok(game)
+    }
+
+    /// The rules this game is played with.
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// The number of pieces in the game, used or not.
+    pub fn piece_count(&self) -> usize {
+        1 << self.field.size()
     }
 
     pub fn round(&self) -> u8 {
-        (((Field::SIZE * Field::SIZE - self.remaining_pieces.len()) / 2) + 1)
+        (((self.piece_count() - self.remaining_pieces.len()) / 2) + 1)
             .try_into()
             .unwrap()
     }
@@ -147,13 +262,72 @@ impl Game {
         }
     }
 
+    /// Returns a key that is the same for all equivalent positions, taking the symmetries of the
+    /// board and the pieces into account. Which player is on the move is not part of the key.
+    pub fn canonical_key(&self) -> CanonicalKey {
+        let tag = match self.status {
+            Status::InitialMove { .. } => 0,
+            Status::Move {
+                claimable: false, ..
+            } => 1,
+            Status::Won { .. } => 2,
+            Status::Draw { .. } => 3,
+            Status::Move {
+                claimable: true, ..
+            } => 4,
+        };
+        symmetry::canonical_key(tag, &self.field, self.next_piece())
+    }
+
+    /// Returns the zobrist hash of this position: the pieces on the field, the piece to place
+    /// next, if any, and if a missed win can be claimed.
+    pub fn zobrist_hash(&self) -> u64 {
+        let hash = self.field.hash();
+        match self.status {
+            Status::Move {
+                next_piece,
+                claimable,
+                ..
+            } => {
+                let hash = hash ^ zobrist::in_hand(next_piece);
+                if claimable {
+                    hash ^ zobrist::CLAIMABLE
+                } else {
+                    hash
+                }
+            }
+            _ => hash,
+        }
+    }
+
     pub fn pp(&self) {
         println!("Quarto, round: {}", self.round());
         println!();
         if self.running() {
+            if self.is_claimable() {
+                println!(
+                    "{} completed a line, but didn't call \"Quarto!\"",
+                    self.player().next()
+                );
+            }
             println!("{}, your move.", self.player());
         } else if let Some(winner) = self.winner() {
             println!("{winner} won!");
+            if self.clock.is_some_and(|clock| clock.flagged(winner.next())) {
+                println!("{} ran out of time.", winner.next());
+            }
+            if let Some(line) = self.field.winning_line() {
+                let base = self.array_base;
+                let line: Vec<String> = line
+                    .iter()
+                    .map(|&(x, y)| format!("{},{}", base.based(x), base.based(y)))
+                    .collect();
+                if self.rules.misere {
+                    println!("The losing line: {}", line.join(" "));
+                } else {
+                    println!("The winning line: {}", line.join(" "));
+                }
+            }
         } else {
             println!("Game ended in a draw!");
         }
@@ -163,6 +337,15 @@ impl Game {
             self.pp_remaining_pieces();
         }
         println!("\nField:");
+        if let Some(clock) = self.clock {
+            println!(
+                "{}: {}, {}: {}",
+                Player::PlayerOne,
+                clock.pp_remaining(Player::PlayerOne),
+                Player::PlayerTwo,
+                clock.pp_remaining(Player::PlayerTwo)
+            );
+        }
         self.field.pp(self.array_base);
 
         if let Some(piece) = self.next_piece() {
@@ -185,7 +368,7 @@ impl Game {
                 print!(" ");
             }
             piece.pp();
-            if i < (Field::SIZE * Field::SIZE) - 1 && (i + 1) % 3 != 0 {
+            if i < self.remaining_pieces.len() - 1 && (i + 1) % 3 != 0 {
                 print!(",  ");
             }
         }
@@ -210,6 +393,7 @@ impl Game {
             self.status = Status::Move {
                 next_player: starting_player.next(),
                 next_piece,
+                claimable: false,
             };
             Ok(())
         } else {
@@ -219,38 +403,97 @@ impl Game {
 
     /// Next move, actually put a piece on the field, and give the next piece to the opponent or
     /// checks if a player won..
+    /// With [`Rules::call_quarto`], completing a line does not win, see [`Game::call_quarto`].
+    /// With [`Rules::misere`], completing a line loses.
     pub fn do_move(&mut self, pos: Pos, next_piece: Piece) -> Result<(), ()> {
         // Grab the curent move that the player wants to execute
         if let Status::Move {
             next_player: player,
             next_piece: piece,
+            ..
         } = self.status
         {
+            // The next piece has to be one of `remaining_pieces`, unless there are none left.
+            let i = self
+                .remaining_pieces()
+                .iter()
+                .position(|&x| x == next_piece);
+            if i.is_none() && !self.remaining_pieces().is_empty() {
+                return Err(());
+            }
+
             // Actually perform the move on the field.
             self.field.put(pos, piece)?;
 
-            if self.remaining_pieces().is_empty() {
-                // This is a draw
-                self.status = Status::Draw {
-                    last_player: player,
-                };
-                return Ok(());
-            }
             // remove the piece from `remaining_pieces`.
-            let i = self
-                .remaining_pieces()
-                .iter()
-                .position(|&x| x == next_piece)
-                .ok_or(())?;
-            self.remaining_pieces.remove(i);
-            // Check if this piece yielded a win for this player.
-            if self.field.check_field_for_win() {
-                self.status = Status::Won { winner: player }
+            if let Some(i) = i {
+                self.remaining_pieces.remove(i);
+            }
+            // Check if this piece completed a line, and thus decided the game.
+            // Lines that were completed earlier, but not called, don't count anymore.
+            let completed_line = self.field.wins_through(pos);
+            if completed_line && !self.rules.call_quarto {
+                self.status = Status::Won {
+                    winner: self.rules.line_winner(player),
+                }
+            } else if i.is_none() || self.field.empty_spaces().is_empty() {
+                // Either the pieces or the field ran out, without a winning line.
+                // A missed win on the last move can't be claimed anymore.
+                self.status = match self.rules.draw.winner(player) {
+                    Some(winner) => Status::Won { winner },
+                    None => Status::Draw {
+                        last_player: player,
+                    },
+                };
             } else {
                 self.status = Status::Move {
                     next_player: player.next(),
                     next_piece,
+                    claimable: completed_line,
                 }
+            }
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    /// Puts the piece to place on `pos`, and calls "Quarto!", winning the game.
+    /// Fails, without changing the game, if the piece doesn't complete a line through `pos`, or
+    /// if the rules don't have [`Rules::call_quarto`].
+    pub fn call_quarto(&mut self, pos: Pos) -> Result<(), ()> {
+        let Status::Move {
+            next_player: player,
+            next_piece: piece,
+            ..
+        } = self.status
+        else {
+            return Err(());
+        };
+        if !self.rules.call_quarto {
+            return Err(());
+        }
+        let mut field = self.field.clone();
+        field.put(pos, piece)?;
+        if !field.wins_through(pos) {
+            return Err(());
+        }
+        self.field = field;
+        self.status = Status::Won { winner: player };
+        Ok(())
+    }
+
+    /// Claims the win that the previous player didn't call, instead of placing the piece.
+    /// Fails if there is no such win.
+    pub fn claim_missed_win(&mut self) -> Result<(), ()> {
+        if let Status::Move {
+            next_player,
+            claimable: true,
+            ..
+        } = self.status
+        {
+            self.status = Status::Won {
+                winner: next_player,
             };
             Ok(())
         } else {
@@ -258,6 +501,99 @@ impl Game {
         }
     }
 
+    /// Charges `player` for the `elapsed` time of the move they just made.
+    /// A player whose time ran out loses, even if their move ended the game otherwise.
+    pub fn charge_clock(&mut self, player: Player, elapsed: Duration) {
+        if let Some(clock) = &mut self.clock {
+            if clock.charge(player, elapsed).is_err() {
+                self.status = Status::Won {
+                    winner: player.next(),
+                };
+            }
+        }
+    }
+
+    /// Sets the time `player` has left after their move, as stored in a record.
+    /// A player without time left loses, as with [`Game::charge_clock`].
+    pub fn set_time_left(&mut self, player: Player, left: Duration) {
+        if let Some(clock) = &mut self.clock {
+            clock.set_remaining(player, left);
+            if left.is_zero() {
+                self.status = Status::Won {
+                    winner: player.next(),
+                };
+            }
+        }
+    }
+
+    /// Returns true if the player to move can claim a win, that the previous player missed.
+    pub fn is_claimable(&self) -> bool {
+        matches!(
+            self.status,
+            Status::Move {
+                claimable: true,
+                ..
+            }
+        )
+    }
+
+    /// Performs a [`Move`], see [`Game::initial_move`], [`Game::do_move`], [`Game::call_quarto`]
+    /// and [`Game::claim_missed_win`].
+    pub fn play_move(&mut self, mv: Move) -> Result<(), ()> {
+        match mv {
+            Move::Initial { next_piece } => self.initial_move(next_piece),
+            Move::Place { pos, next_piece } => self.do_move(pos, next_piece),
+            Move::Quarto { pos } => self.call_quarto(pos),
+            Move::Claim => self.claim_missed_win(),
+        }
+    }
+
+    /// Returns all legal moves in the current position.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        match self.status {
+            Status::InitialMove { .. } => self
+                .remaining_pieces
+                .iter()
+                .map(|&next_piece| Move::Initial { next_piece })
+                .collect(),
+            Status::Move {
+                next_piece,
+                claimable,
+                ..
+            } => {
+                let next_pieces = if self.remaining_pieces.is_empty() {
+                    vec![next_piece]
+                } else {
+                    self.remaining_pieces.clone()
+                };
+                let mut moves: Vec<Move> = self
+                    .field
+                    .empty_spaces()
+                    .into_iter()
+                    .flat_map(|pos| {
+                        next_pieces
+                            .iter()
+                            .map(move |&next_piece| Move::Place { pos, next_piece })
+                    })
+                    .collect();
+                if self.rules.call_quarto {
+                    for pos in self.field.empty_spaces() {
+                        let mut field = self.field.clone();
+                        field.put(pos, next_piece).unwrap();
+                        if field.wins_through(pos) {
+                            moves.push(Move::Quarto { pos });
+                        }
+                    }
+                    if claimable {
+                        moves.push(Move::Claim);
+                    }
+                }
+                moves
+            }
+            Status::Won { .. } | Status::Draw { .. } => vec![],
+        }
+    }
+
     /// Undo the latest move
     #[cfg(test)]
     pub fn unmove(&mut self, last_pos: Pos) {
@@ -276,7 +612,7 @@ impl Game {
         let last_piece = self.field.clear(last_pos).unwrap();
         self.remaining_pieces.push(last_piece);
 
-        if self.remaining_pieces.len() == Field::SIZE * Field::SIZE {
+        if self.remaining_pieces.len() == self.piece_count() {
             self.status = Status::InitialMove {
                 starting_player: next_player,
             }
@@ -284,18 +620,25 @@ impl Game {
             self.status = Status::Move {
                 next_piece: last_piece,
                 next_player: prev_player,
+                claimable: false,
             }
-        };
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Game, Player};
+    use super::{Game, Player, Status};
+    use crate::{
+        ai::RandomAi,
+        field::Field,
+        rules::{DrawRule, Rules, WinLines},
+        strategy::Strategy,
+    };
 
     #[test]
     fn test_move_unmove() {
-        let mut game = Game::new(Player::PlayerOne);
+        let mut game = Game::new(Player::PlayerOne, Rules::CLASSIC);
         let post_unmove = game.field.clone();
         game.initial_move(game.remaining_pieces()[0]).unwrap();
         game.do_move((0, 0), game.remaining_pieces()[1]).unwrap();
@@ -303,4 +646,133 @@ mod tests {
         game.unmove((0, 0));
         assert_eq!(post_unmove, game.field);
     }
+
+    #[test]
+    fn test_zobrist_hash() {
+        let mut game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        let empty_hash = game.zobrist_hash();
+        game.initial_move(game.remaining_pieces()[3]).unwrap();
+        let initial_hash = game.zobrist_hash();
+        assert_ne!(empty_hash, initial_hash);
+
+        game.do_move((1, 2), game.remaining_pieces()[5]).unwrap();
+        game.do_move((3, 0), game.remaining_pieces()[0]).unwrap();
+
+        // The same position, reached in a different order.
+        let mut other = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        other.initial_move(game.field.get((3, 0)).unwrap()).unwrap();
+        other
+            .do_move((3, 0), game.field.get((1, 2)).unwrap())
+            .unwrap();
+        other.do_move((1, 2), game.next_piece().unwrap()).unwrap();
+        assert_eq!(game.zobrist_hash(), other.zobrist_hash());
+
+        // Recomputing the hash from scratch gives the same result.
+        let mut field = Field::new();
+        field.put((1, 2), game.field.get((1, 2)).unwrap()).unwrap();
+        field.put((3, 0), game.field.get((3, 0)).unwrap()).unwrap();
+        assert_eq!(field.hash(), game.field.hash());
+
+        game.unmove((3, 0));
+        game.unmove((1, 2));
+        assert_eq!(game.field.hash(), Field::new().hash());
+    }
+
+    #[test]
+    fn test_legal_moves() {
+        let mut game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        assert_eq!(game.legal_moves().len(), 16);
+        game.play_move(game.legal_moves()[3]).unwrap();
+        assert_eq!(game.legal_moves().len(), 16 * 15);
+        game.play_move(game.legal_moves()[17]).unwrap();
+        assert_eq!(game.legal_moves().len(), 15 * 14);
+    }
+
+    #[test]
+    fn test_sizes() {
+        for size in Field::MIN_SIZE..=Field::MAX_SIZE {
+            let mut game = Game::new(
+                Player::PlayerOne,
+                Rules {
+                    size,
+                    ..Rules::CLASSIC
+                },
+            );
+            assert_eq!(game.legal_moves().len(), 1 << size);
+            let mut ai = RandomAi::with_seed(size as u64);
+            let mut plies = 0;
+            while game.running() {
+                game = ai.play(&game);
+                plies += 1;
+            }
+            // Either the field or the pieces run out.
+            assert!(plies <= (size * size).min(1 << size) + 1);
+        }
+    }
+
+    #[test]
+    fn test_draw_rule() {
+        let rules = Rules {
+            lines: WinLines {
+                diagonals: false,
+                ..WinLines::CLASSIC
+            },
+            draw: DrawRule::LastPlayerLoses,
+            ..Rules::CLASSIC
+        };
+        let mut full_fields = 0;
+        for seed in 0..100 {
+            let mut game = Game::new(Player::PlayerOne, rules);
+            let mut ai = RandomAi::with_seed(seed);
+            let mut last_player = game.player();
+            while game.running() {
+                last_player = game.player();
+                game = ai.play(&game);
+            }
+            assert!(!matches!(game.status, Status::Draw { .. }));
+            if !game.field.check_field_for_win() {
+                full_fields += 1;
+                assert_eq!(game.winner(), Some(last_player.next()));
+            }
+        }
+        assert!(full_fields > 0);
+    }
+
+    #[test]
+    fn test_call_quarto() {
+        use crate::{notation::parse_notation, piece::Piece};
+
+        // Player one can complete the first row by placing 7 on (3, 0).
+        let game = parse_notation("135./..../..../.... 7 1 variants=call-quarto").unwrap();
+        assert_eq!(game.legal_moves().len(), 13 * 12 + 1);
+
+        let mut called = game.clone();
+        assert!(called.call_quarto((0, 1)).is_err());
+        assert_eq!(called.status, game.status);
+        assert_eq!(called.field, game.field);
+        called.call_quarto((3, 0)).unwrap();
+        assert_eq!(called.winner(), Some(Player::PlayerOne));
+
+        // Without the call, player two can claim the win, but only right away.
+        let mut missed = game;
+        missed.do_move((3, 0), Piece::with_props(0)).unwrap();
+        assert!(missed.running() && missed.is_claimable());
+        assert!(missed.legal_moves().contains(&super::Move::Claim));
+        let mut claimed = missed.clone();
+        claimed.claim_missed_win().unwrap();
+        assert_eq!(claimed.winner(), Some(Player::PlayerTwo));
+
+        missed.do_move((0, 1), Piece::with_props(2)).unwrap();
+        assert!(missed.running() && !missed.is_claimable());
+        assert!(missed.claim_missed_win().is_err());
+    }
+
+    #[test]
+    fn test_misere() {
+        use crate::{notation::parse_notation, piece::Piece};
+
+        let mut game = parse_notation("135./..../..../.... 7 1 variants=misere").unwrap();
+        game.do_move((3, 0), Piece::with_props(0)).unwrap();
+        assert_eq!(game.winner(), Some(Player::PlayerTwo));
+    }
 }
@@ -24,7 +24,6 @@ Welcome to `quarto_rs`
     unused_import_braces,
     unused_qualifications,
     unused_must_use,
-    missing_docs,
     //unused_results
 )]
 #![cfg_attr(
@@ -47,68 +46,88 @@ Welcome to `quarto_rs`
 )]
 
 mod ai;
+mod analysis;
+mod book;
+mod clock;
+mod endgames;
+mod eval;
 mod field;
 mod game;
+#[cfg(feature = "nn")]
+mod nn;
+mod notation;
+mod perft;
 mod piece;
+mod record;
 mod rng;
+mod rules;
+mod search;
+mod simulation;
+mod sprt;
+mod strategy;
+mod symmetry;
+mod tournament;
+mod tt;
+mod tune;
+mod tutor;
+mod zobrist;
 
-use std::{env::args, io::stdin};
+use std::{env::args, io::stdin, str::FromStr, time::Instant};
 
 use game::ArrayBase;
 
 use crate::{
     ai::SimpleAi,
-    field::{try_parse_pos, Field},
-    game::{Game, Player, Status},
+    book::Book,
+    clock::{Clock, TimeControl},
+    endgames::EndgameTable,
+    eval::{Features, Weights},
+    field::{try_parse_pos, Pos},
+    game::{Game, Move, Player},
+    notation::parse_notation_with,
     piece::Piece,
+    record::Record,
     rng::{time_nanos, RomuDuoJrRand},
+    rules::Rules,
+    simulation::Format,
+    strategy::{Strategy, StrategySpec},
+    tune::Spsa,
+    tutor::Tutor,
 };
 
 fn main() {
     if args().any(|x| x.contains("help") || x == "-h") {
-        let current_exe = std::env::current_exe().unwrap();
-        let current_exe_name = current_exe.file_name().unwrap().to_string_lossy();
-        println!(
-            "Your friendly Quarto game.
-
-The game is played on a 4x4 board with 16 pieces. Each piece has four distinct
-characteristics: size (large/✋ or small/🤏), color (light/⬜ or dark/⬛),
-shape (round/🟠 or square/🔶), and fill (filled/🔴 or hollow/⭕).
-On your turn, you choose one of the 16 pieces and give it to your opponent.
-Your opponent then places that piece on any empty space on the board.
-
-The first player to create a row of four pieces with at least one matching
-characteristic wins the game. Matching characteristics can be in any direction,
-horizontally, vertically, or diagonally.
-In the harder square mode, (-q), a square of 4 is also considered a win.
-
-If all 16 pieces have been placed and there is no winner, the game is a tie.
-
-Usage: {current_exe_name} <Options>
-
-Options:
-    --square-mode|-q:   Enable harder rules: not only 4 of the same in a row,
-                        but also a square of 4 is considered a win.
-    --base0|-0:         Starts to count at 0 instead of 1 (programmer style)
-    --ai-reasoning|-r:  Print information about what the AI is doing, and why,
-                        during the game.
-    --ai-simulation|-a: Simulate a bunch of AI battles.
-    --seed=<>|-s=<>:    Seed the AI RNG
-    --pvp|-p            No AI, just humans (player vs player)
-    --help|-h:          Print this help screen.
-
-Good luck!
-"
-        );
+        print_help();
         return;
     }
 
-    let mut game = Game::new(Player::PlayerOne);
-
-    if args().any(|x| x == "--ai-reasoning" || x == "-r") {
-        game.ai_reasoning = true;
+    let rules = match rules_from_args() {
+        Ok(rules) => rules,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    let mut game = Game::new(Player::PlayerOne, rules);
+    match parse_arg::<TimeControl>("--clock", "-C") {
+        Ok(control) => game.clock = control.map(Clock::new),
+        Err(control_str) => {
+            println!("Invalid time control: {control_str}, e.g. 5+3 for 5 minutes, plus 3 seconds per move");
+            return;
+        }
     }
 
+    let mut options = Options {
+        ai_reasoning: args().any(|x| x == "--ai-reasoning" || x == "-r"),
+        seed: None,
+        pvp: args().any(|x| x == "--pvp" || x == "-p"),
+        teach: args().any(|x| x == "--teach" || x == "-T"),
+        tutor: args().any(|x| x == "--tutor" || x == "-u"),
+        threads: 1,
+        opponent: StrategySpec::Simple,
+        ponder: args().any(|x| x == "--ponder" || x == "-k"),
+    };
+
     if let Some(seed) = args().find(|x| x.starts_with("--seed") || x.starts_with("-s=")) {
         let mut seed = seed.split('=');
         let _ = seed.next();
@@ -117,86 +136,506 @@ Good luck!
             println!("Invalid seed: {seed_str}");
             return;
         };
-        game.seed = Some(seed);
-    }
-
-    if args().any(|x| x == "--square-mode" || x == "-q") {
-        game.field.square_mode = true;
+        options.seed = Some(seed);
     }
 
     if args().any(|x| x == "--base0" || x == "-0") {
         game.array_base = ArrayBase::Zero;
     }
 
-    if args().any(|x| x == "--pvp" || x == "-p") {
-        game.pvp = true;
+    // Reasoning output of parallel games would be interleaved, so we default to one thread then.
+    if !options.ai_reasoning {
+        options.threads = std::thread::available_parallelism().map_or(1, usize::from);
+    }
+    match parse_arg("--threads", "-j") {
+        Ok(Some(parsed)) => options.threads = parsed,
+        Ok(None) => {}
+        Err(threads_str) => {
+            println!("Invalid number of threads: {threads_str}");
+            return;
+        }
+    }
+
+    if let Err(err) = load_tables(*game.rules()) {
+        println!("{err}");
+        return;
+    }
+
+    // The nn strategy is only available once the network is loaded.
+    match parse_arg("--opponent", "-o") {
+        Ok(Some(parsed)) => options.opponent = parsed,
+        Ok(None) => {}
+        Err(strategy_str) => {
+            println!(
+                "Invalid strategy: {strategy_str}, available: {}",
+                StrategySpec::NAMES
+            );
+            return;
+        }
+    }
+
+    match args().nth(1).as_deref() {
+        Some("book") => return build_book(&game, &options),
+        Some("endgames") => return build_endgames(&game, &options),
+        Some("tournament") => return tournament(&game, &options),
+        Some("sprt") => return sprt(&game, &options),
+        Some("analyze") => return analyze(&game, &options),
+        Some("perft") => return perft(&game, &options),
+        Some("eval") => return eval(&game),
+        Some("tune") => return tune(&game, &options),
+        #[cfg(feature = "nn")]
+        Some("nn") => return train_network(&game, &options),
+        _ => {}
     }
 
     if args().any(|x| x == "--ai-simulation" || x == "-a") {
-        if game.pvp {
+        if options.pvp {
             println!("PvP mode and ai-simulation don't match.. :)");
-        } else {
-            ai_simulation(&game);
+            return;
         }
+        ai_simulation(&game, &options);
         return;
     }
 
-    play(game);
+    play(game, &options);
 }
 
-fn play(mut game: Game) {
-    let mut buf = String::new();
-    #[allow(clippy::cast_possible_truncation)]
-    let seed = game.seed.unwrap_or_else(|| time_nanos() as u64);
+/// Reads the [`Rules`] from `--rules`, and the flags applied on top of them.
+fn rules_from_args() -> Result<Rules, String> {
+    let mut rules = match parse_arg::<String>("--rules", "-R") {
+        Ok(Some(rules_str)) => rules_str
+            .parse()
+            .map_err(|err| format!("Invalid rules: {err}"))?,
+        _ => Rules::CLASSIC,
+    };
+    match parse_arg("--size", "-S") {
+        Ok(Some(size)) => rules.size = size,
+        Ok(None) => {}
+        Err(size_str) => return Err(format!("Invalid size: {size_str}")),
+    }
+    if args().any(|x| x == "--square-mode" || x == "-q") {
+        rules.lines.squares = true;
+    }
+    if args().any(|x| x == "--torus" || x == "-t") {
+        rules.make_toroidal();
+    }
+    if args().any(|x| x == "--call-quarto" || x == "-c") {
+        rules.call_quarto = true;
+    }
+    if args().any(|x| x == "--misere" || x == "-m") {
+        rules.misere = true;
+    }
+    rules
+        .validate()
+        .map_err(|err| format!("Invalid rules: {err}"))?;
+    Ok(rules)
+}
+
+/// The command line options that are not part of the [`Rules`] of the game.
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+struct Options {
+    /// Print what the AI is doing, and why.
+    ai_reasoning: bool,
+    /// The seed of the AI RNG, random if it's not given.
+    seed: Option<u64>,
+    /// No AI, just humans.
+    pvp: bool,
+    /// A weaker AI, undos, and warnings before giving away a win.
+    teach: bool,
+    /// Judge each move of a human.
+    tutor: bool,
+    /// The number of threads for simulations.
+    threads: usize,
+    /// The strategy of the AI in a game against a human.
+    opponent: StrategySpec,
+    /// Let the AI think during the turns of the human.
+    ponder: bool,
+}
+
+impl Options {
+    /// The given seed, or a random one.
+    fn seed(&self) -> u64 {
+        #[allow(clippy::cast_possible_truncation)]
+        self.seed.unwrap_or_else(|| time_nanos() as u64)
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn print_help() {
+    let current_exe = std::env::current_exe().unwrap();
+    let current_exe_name = current_exe.file_name().unwrap().to_string_lossy();
+    println!(
+        "Your friendly Quarto game.
+
+The game is played on a 4x4 board with 16 pieces. Each piece has four distinct
+characteristics: size (large/✋ or small/🤏), color (light/⬜ or dark/⬛),
+shape (round/🟠 or square/🔶), and fill (filled/🔴 or hollow/⭕).
+On your turn, you choose one of the 16 pieces and give it to your opponent.
+Your opponent then places that piece on any empty space on the board.
+
+The first player to create a row of four pieces with at least one matching
+characteristic wins the game. Matching characteristics can be in any direction,
+horizontally, vertically, or diagonally.
+In the harder square mode, (-q), a square of 4 is also considered a win.
+On a torus (-t), lines wrap around the edges of the board, so broken diagonals
+(and, in square mode, squares across the edges) win, too.
+With the call rule (-c), you have to call \"Quarto!\" when completing a line.
+If you miss it, your opponent can claim the win before placing their piece.
+In misère Quarto (-m), it's the other way around: whoever completes a line loses.
+
+If all 16 pieces have been placed and there is no winner, the game is a tie.
+
+Usage: {current_exe_name} <Options>
+   {current_exe_name} tournament <strategy> <strategy>... <Options>
+   {current_exe_name} sprt <strategy A> <strategy B> <Options>
+   {current_exe_name} analyze <game record> <Options>
+   {current_exe_name} book build <file> <Options>
+   {current_exe_name} endgames build <file> <Options>
+   {current_exe_name} perft <depth> <Options>
+   {current_exe_name} eval <position> <Options>
+   {current_exe_name} tune <file> <Options>
+   {current_exe_name} nn train <file> <Options> (with the nn feature)
+
+Options:
+--square-mode|-q:   Enable harder rules: not only 4 of the same in a row,
+                    but also a square of 4 is considered a win.
+--torus|-t:         Lines wrap around the edges: broken diagonals, and with -q
+                    squares across the edges, are considered a win.
+--call-quarto|-c:   A completed line only wins if you call \"Quarto!\", or
+                    else your opponent can claim the win.
+--misere|-m:        Completing a line loses the game.
+--clock=<>|-C=<>:   Play with a clock, e.g. 5+3 for 5 minutes per player, plus
+                    3 seconds per move. Whoever runs out of time loses.
+--rules=<>|-R=<>:   The rules, as key=value pairs separated by ',':
+                    size=<4>, lines=<rows+diagonals> (any of rows, diagonals,
+                    broken-diagonals, squares and wrapping-squares, joined
+                    by '+'), draw=<draw> (or last-player-wins,
+                    last-player-loses) and variants=<> (call-quarto or
+                    misere). -q, -t, -c, -m and -S are applied on top.
+--size=<>|-S=<>:    Play on a NxN board with the 2^N pieces of N characteristics
+                    (3 to 6, default: 4). Squares stay 2x2.
+--base0|-0:         Starts to count at 0 instead of 1 (programmer style)
+--ai-reasoning|-r:  Print information about what the AI is doing, and why,
+                    during the game.
+--ai-simulation|-a: Simulate a bunch of AI battles.
+--games=<>|-n=<>:   Number of games for the ai-simulation (default: 100),
+                    or per pair of strategies in a tournament (default: 20)
+--player-one=<>|-1=<>, --player-two=<>|-2=<>:
+                    The strategy of each player in the ai-simulation:
+                    simple (default), random, or
+                    search[:depth=<2>,time=<ms>,threads=<1>], searching up
+                    to depth moves ahead, in at most time milliseconds per
+                    move, on the given number of threads.
+--format=<>|-f=<>:  Output of the ai-simulation: text (default), json or csv.
+--position=<>|-P=<>: Start all games of the ai-simulation from this position,
+                    e.g. \"9.../.0../..../.... f 1\" (rows, piece to place
+                    next, player to move).
+--threads=<>|-j=<>: Number of threads for the ai-simulation and tournaments
+                    (default: all cores)
+--book=<>|-B=<>:    Load an opening book, built with `book build`, that all
+                    AIs play from in the first plies.
+--endgames=<>|-E=<>: Load an endgame table, built with `endgames build`,
+                    that all AIs play the endgames in it from.
+--weights=<>|-W=<>: Load the weights of the evaluation of the search AI from a
+                    file of name = value lines, see `eval`.
+--net=<>|-N=<>:     Load a network, trained with `nn train`, for the nn strategy.
+--seed=<>|-s=<>:    Seed the AI RNG
+--pvp|-p            No AI, just humans (player vs player)
+--teach|-T:         Teaching mode: the AI looks at fewer moves, you can undo your
+                    moves, and you are warned before giving away a win.
+--tutor|-u:         Judge each of your moves, and sum up your mistakes at the end.
+--opponent=<>|-o=<>: The strategy of the AI you play against, see --player-one
+                    (default: simple). Teaching mode always uses simple.
+--ponder|-k:        Let the AI think during your turns, to move faster and
+                    better on its own. Only search strategies ponder.
+--help|-h:          Print this help screen.
+
+Tournament:
+Plays each pair of strategies against each other, and prints a cross table
+with the score of each strategy against each other one, and their Elo.
+
+SPRT:
+Plays strategy A against B until a sequential probability ratio test decides
+between H1: A is elo1 stronger than B, and H0: A is elo0 stronger than B.
+--elo0=<>|-e0=<>:   Elo difference of H0 (default: 0)
+--elo1=<>|-e1=<>:   Elo difference of H1 (default: 10)
+--alpha=<>|-a=<>:   Probability to accept H1 if H0 holds (default: 0.05)
+--beta=<>|-b=<>:    Probability to accept H0 if H1 holds (default: 0.05)
+--games=<>|-n=<>:   Give up after this many games (default: 100000)
+
+Analyze:
+Replays a game record, as printed at the end of a game, and judges each move:
+its evaluation, better alternatives, the move that lost the game, and how many
+of the remaining pieces complete a line.
+--depth=<>|-d=<>:   How many moves deep to search (default: 3)
+--format=<>|-f=<>:  text (default) or json
+
+Book:
+Builds an opening book for the rules, by searching each position of the first
+plies, up to symmetry, and writes it to the file. Load it with --book.
+--plies=<>|-l=<>:   How many plies the book covers (default: 3)
+--depth=<>|-d=<>:   How many moves deep to search each position (default: 4)
+
+Endgame table:
+Solves all reachable positions with few empty squares, up to symmetry, and
+writes them to the file. Where those are too many to enumerate, as on the
+classic field, plays games between simple AIs until few squares are left, and
+only solves the positions that are reachable from there. Load it with
+--endgames.
+--empty=<>|-e=<>:   The most empty squares of a solved position (default: 5)
+--games=<>|-n=<>:   How many games to sample endgames from, if they are too
+                    many to enumerate (default: 1000)
+
+Perft:
+Counts the legal move sequences of each length up to the depth, and how many
+per second were played, to validate the move generation.
+--position=<>|-P=<>: Count from this position instead of the start.
+--symmetry|-y:      Count the distinct positions, up to symmetry, instead.
+
+Eval:
+Prints the features of a position, e.g. \"e8c./..../..../.... 3 2\", and how
+the weights of the evaluation of the search AI score them.
+
+Tune:
+Tunes the weights of the evaluation by self-play of search AIs, with SPSA,
+starting from those given with --weights, and writes them to the file.
+The result only depends on the seed (default: 0).
+--iterations=<>|-i=<>: How many times to adjust the weights (default: 50)
+--games=<>|-n=<>:   Games per iteration (default: 40)
+--depth=<>|-d=<>:   How many moves deep the AIs search (default: 1)
+
+NN (with the nn feature):
+Trains a small neural network on the outcomes of self-play games of search AIs,
+and writes it to the file. With --net, the nn[:depth=<2>] strategy searches
+with the network as evaluation.
+--games=<>|-n=<>:   Self-play games to learn from (default: 500)
+--epochs=<>|-e=<>:  Passes over all positions (default: 20)
+--hidden=<>|-H=<>:  Hidden neurons (default: 32)
+--depth=<>|-d=<>:   How many moves deep the self-play AIs search (default: 1)
+
+Good luck!
+"
+    );
+}
+
+fn play(mut game: Game, options: &Options) {
+    let seed = options.seed();
 
     println!("Game Seed: {seed}");
 
     let human = RomuDuoJrRand::with_seed(seed).choose([Player::PlayerOne, Player::PlayerTwo]);
-    let mut ai = SimpleAi::with_seed(human.next(), seed);
+    // The opponents are built with the opening book and endgame table, except the handicapped one
+    // of teaching mode.
+    let mut ai: Box<dyn Strategy> = if options.teach {
+        Box::new(
+            SimpleAi::with_seed(human.next(), seed)
+                .with_reasoning(options.ai_reasoning)
+                .with_budget(TEACH_BUDGET),
+        )
+    } else {
+        options
+            .opponent
+            .build(human.next(), seed, options.ai_reasoning)
+    };
+    let mut record = Record::new(game.clone());
+    // The positions at the start of each turn of a human, and the number of moves played until
+    // then, to undo moves in teaching mode.
+    let mut history: Vec<(Game, usize)> = vec![];
+    let mut tutor = options.tutor.then(|| Tutor::new(TUTOR_DEPTH, seed));
 
-    if !game.pvp {
+    if !options.pvp {
         println!("You are {human}.");
     }
 
     println!();
     println!("Let the games begin!");
 
-    loop {
+    'game: loop {
         game.pp();
         if !game.running() {
+            if let Some(tutor) = &tutor {
+                println!();
+                tutor.pp_summary();
+            }
+            println!("\nGame record, see `analyze`:\n{record}");
             return;
         }
 
-        if game.pvp || game.player() == human {
-            if game.is_initial_move() {
+        let player = game.player();
+        let start = Instant::now();
+        let before = game.clone();
+        let human_turn = options.pvp || player == human;
+        let mv = if human_turn {
+            if options.teach {
+                history.push((game.clone(), record.moves.len()));
+            }
+            // Stops when the move of the human is done.
+            let _ponder = (options.ponder && !options.pvp)
+                .then(|| ai.ponder(&game))
+                .flatten();
+            let mv = if game.is_claimable() && ask("Claim the win?") {
+                game.claim_missed_win().unwrap();
+                Move::Claim
+            } else if game.is_initial_move() {
                 let next_piece = read_piece(&game);
                 game.initial_move(next_piece).unwrap();
+                Move::Initial { next_piece }
+            } else if let Some(mv) = play_placement(&mut game, options.teach) {
+                mv
             } else {
-                loop {
-                    println!("Select x,y to put the piece to:");
-                    buf.clear();
-                    stdin().read_line(&mut buf).unwrap();
-                    let base = game.array_base;
-                    let pos = try_parse_pos(&buf).map(|(x, y)| (base.unbased(x), base.unbased(y)));
-                    if let Ok(pos) = pos {
-                        if pos.0 < Field::SIZE && pos.1 < Field::SIZE {
-                            let next_piece = read_piece(&game);
-                            if game.do_move(pos, next_piece).is_ok() {
-                                break;
-                            }
-                        }
+                // Drop this turn, and go back to the start of the previous one.
+                history.pop();
+                match history.pop() {
+                    Some((previous, moves)) => {
+                        game = previous;
+                        record.truncate(moves);
                     }
-                    println!("Illegal move! The x,y value must be an empty place on the field!");
-                    println!();
+                    None => println!("There is nothing to undo yet."),
                 }
-            }
+                continue 'game;
+            };
             println!();
+            if game
+                .clock
+                .is_some_and(|clock| start.elapsed() >= clock.remaining(player))
+            {
+                // The flag fell while the move was entered, so it is not played.
+                game = before.clone();
+                game.charge_clock(player, start.elapsed());
+                continue 'game;
+            }
+            mv
+        } else {
+            game = ai.play(&game);
+            Record::find_move(&before, &game).expect("AI should only do legal moves")
+        };
+        game.charge_clock(player, start.elapsed());
+        record.push(mv, player, &game);
+
+        if let Some(tutor) = tutor.as_mut().filter(|_| human_turn) {
+            if let Some(verdict) = tutor.judge(&before, mv) {
+                println!("Tutor: {verdict}");
+            }
+        }
+    }
+}
+
+/// How many plies a book covers, unless given.
+const BOOK_PLIES: u8 = 3;
+
+/// How many moves deep the positions of a book are searched, unless given.
+const BOOK_DEPTH: u8 = 4;
+
+/// How many empty squares the positions of an endgame table have at most, unless given.
+const ENDGAMES_EMPTY: u8 = 5;
+
+/// How many sampled games an endgame table is built from, unless given.
+const ENDGAMES_GAMES: usize = 1000;
+
+/// The SPSA parameters of `tune`, unless given.
+const TUNE: Spsa = Spsa {
+    iterations: 50,
+    games: 40,
+    depth: 1,
+    a: 20.,
+    c: 2.,
+};
+
+/// The training parameters of `nn train`, unless given.
+#[cfg(feature = "nn")]
+const TRAINING: nn::Training = nn::Training {
+    games: 500,
+    depth: 1,
+    epochs: 20,
+    hidden: 32,
+    learning_rate: 0.005,
+};
+
+/// How many moves deep the tutor searches.
+const TUTOR_DEPTH: u8 = 3;
+
+/// How many moves deep the analysis of a game record searches, unless given.
+const ANALYSIS_DEPTH: u8 = 3;
+
+/// The number of empty spaces the AI looks at in teaching mode, see [`SimpleAi::with_budget`].
+const TEACH_BUDGET: usize = 4;
+
+/// Returns where the opponent could complete a line with `next_piece`, after the piece to place
+/// is put on `pos`, unless the game is already decided by then.
+/// In misère, completing a line loses, so there is no such thing as a poisoned piece.
+fn poisoned_piece(game: &Game, pos: Pos, next_piece: Piece) -> Option<Pos> {
+    let mut field = game.field.clone();
+    field.put(pos, game.next_piece()?).ok()?;
+    if field.wins_through(pos) || game.rules().misere || field.empty_spaces().is_empty() {
+        return None;
+    }
+    SimpleAi::find_win(&field, next_piece)
+}
+
+/// Reads where a human puts the piece, and which piece they give to the opponent, and plays
+/// that move. Returns `None` if the human wants to undo their last move, in teaching mode.
+fn play_placement(game: &mut Game, teach: bool) -> Option<Move> {
+    let mut buf = String::new();
+    loop {
+        if teach {
+            println!("Select x,y to put the piece to (or u to undo your last move):");
         } else {
-            game = ai.play_iteratively(&mut game);
+            println!("Select x,y to put the piece to:");
+        }
+        buf.clear();
+        stdin().read_line(&mut buf).unwrap();
+        if teach && buf.trim() == "u" {
+            return None;
         }
+        let base = game.array_base;
+        let pos = try_parse_pos(&buf).map(|(x, y)| (base.unbased(x), base.unbased(y)));
+        if let Ok(pos) = pos {
+            if pos.0 < game.field.size()
+                && pos.1 < game.field.size()
+                && game.field.get(pos).is_none()
+            {
+                if game.rules().call_quarto && ask("Call \"Quarto!\"?") {
+                    if game.call_quarto(pos).is_ok() {
+                        return Some(Move::Quarto { pos });
+                    }
+                    println!("That's no Quarto, the piece is placed anyway.");
+                }
+                let next_piece = loop {
+                    let next_piece = read_piece(game);
+                    let warning = teach
+                        .then(|| poisoned_piece(game, pos, next_piece))
+                        .flatten();
+                    let Some((x, y)) = warning else {
+                        break next_piece;
+                    };
+                    if ask(&format!(
+                        "Are you sure? This lets {} win at {},{}.",
+                        game.player().next(),
+                        base.based(x),
+                        base.based(y)
+                    )) {
+                        break next_piece;
+                    }
+                };
+                if game.do_move(pos, next_piece).is_ok() {
+                    return Some(Move::Place { pos, next_piece });
+                }
+            }
+        }
+        println!("Illegal move! The x,y value must be an empty place on the field!");
+        println!();
     }
 }
 
+/// Asks a yes or no question, defaulting to no.
+fn ask(question: &str) -> bool {
+    println!("{question} (y/N)");
+    let mut buf = String::new();
+    stdin().read_line(&mut buf).unwrap();
+    matches!(buf.trim(), "y" | "Y" | "yes")
+}
+
 fn read_piece(game: &Game) -> Piece {
     let mut buf = String::with_capacity(16);
     let base = game.array_base;
@@ -224,76 +663,529 @@ fn read_piece(game: &Game) -> Piece {
     game.remaining_pieces()[piece_id]
 }
 
-#[allow(clippy::cast_precision_loss, clippy::cast_lossless)]
-fn ai_simulation(base_game: &Game) {
-    const ITERS: usize = 100;
+/// Returns the value of a `--long=<value>` or `-short=<value>` argument, if it was given.
+/// If the value can't be parsed, it is returned as error.
+fn parse_arg<T: FromStr>(long: &str, short: &str) -> Result<Option<T>, String> {
+    let Some(value) = args().find_map(|arg| {
+        let (name, value) = arg.split_once('=')?;
+        (name == long || name == short).then(|| value.to_string())
+    }) else {
+        return Ok(None);
+    };
+    value.parse().map(Some).map_err(|_| value)
+}
 
-    let it = std::time::Instant::now();
+fn ai_simulation(base_game: &Game, options: &Options) {
+    let games = match parse_arg("--games", "-n") {
+        Ok(games) => games.unwrap_or(100),
+        Err(games_str) => {
+            println!("Invalid number of games: {games_str}");
+            return;
+        }
+    };
+    let mut strategies = [StrategySpec::Simple, StrategySpec::Simple];
+    for (strategy, (long, short)) in strategies
+        .iter_mut()
+        .zip([("--player-one", "-1"), ("--player-two", "-2")])
+    {
+        match parse_arg(long, short) {
+            Ok(Some(parsed)) => *strategy = parsed,
+            Ok(None) => {}
+            Err(strategy_str) => {
+                println!(
+                    "Invalid strategy: {strategy_str}, available: {}",
+                    StrategySpec::NAMES
+                );
+                return;
+            }
+        }
+    }
+    let format = match parse_arg("--format", "-f") {
+        Ok(format) => format.unwrap_or(Format::Text),
+        Err(format_str) => {
+            println!("Invalid format: {format_str}, available: text, json, csv");
+            return;
+        }
+    };
+    let base_game = match parse_arg::<String>("--position", "-P") {
+        Ok(Some(position)) => {
+            // Positions without rules or clock are played with those from the command line.
+            let Ok(mut game) = parse_notation_with(&position, *base_game.rules()) else {
+                println!("Invalid position: {position}");
+                return;
+            };
+            game.clock = game.clock.or(base_game.clock);
+            game
+        }
+        _ => base_game.clone(),
+    };
+    let seed = options.seed();
+    let config = simulation::Config::new(&base_game, strategies, seed, games, options.threads);
+    if format == Format::Text {
+        println!("Using seed {} on {} threads", config.seed, config.threads);
+    }
 
-    let mut ai_one_wins = 0;
-    let mut ai_two_wins = 0;
-    let mut turns = 0_u64;
+    let it = Instant::now();
+    let results = simulation::run(&base_game, &config, options.ai_reasoning);
+    simulation::report(format, &config, &results, it.elapsed());
+}
 
-    #[allow(clippy::cast_possible_truncation)]
-    let seed = base_game.seed.unwrap_or_else(|| time_nanos() as u64);
-    let mut rng = RomuDuoJrRand::with_seed(seed);
+fn tournament(base_game: &Game, options: &Options) {
+    let mut strategies = vec![];
+    for strategy_str in args().skip(2).filter(|arg| !arg.starts_with('-')) {
+        let Ok(strategy) = strategy_str.parse() else {
+            println!(
+                "Invalid strategy: {strategy_str}, available: {}",
+                StrategySpec::NAMES
+            );
+            return;
+        };
+        strategies.push(strategy);
+    }
+    if strategies.len() < 2 {
+        println!("A tournament needs at least two strategies.");
+        return;
+    }
+    let games = match parse_arg("--games", "-n") {
+        Ok(games) => games.unwrap_or(20),
+        Err(games_str) => {
+            println!("Invalid number of games: {games_str}");
+            return;
+        }
+    };
+    let seed = options.seed();
+    println!(
+        "Using seed {seed}, {games} games per pair on {} threads",
+        options.threads
+    );
 
-    println!("Using seed {seed}");
+    let table = tournament::run(
+        base_game,
+        &strategies,
+        games,
+        seed,
+        options.threads,
+        options.ai_reasoning,
+    );
+    println!();
+    table.pp();
+}
 
-    'outer: for _ in 0..ITERS {
-        let mut game = base_game.clone();
+/// Loads the opening book given with `--book`, and the endgame table given with
+/// `--endgames`, if any, for games with `rules`, and the evaluation weights given with
+/// `--weights`, and installs them.
+fn load_tables(rules: Rules) -> Result<(), String> {
+    let open = |path: &str| {
+        std::fs::File::open(path)
+            .map(std::io::BufReader::new)
+            .map_err(|err| format!("Can't read {path}: {err}"))
+    };
+    if let Ok(Some(path)) = parse_arg::<String>("--book", "-B") {
+        let book = Book::read(open(&path)?).map_err(|err| format!("Invalid book {path}: {err}"))?;
+        if book.rules() != &rules {
+            return Err(format!(
+                "The book {path} is for other rules: {}",
+                book.rules()
+            ));
+        }
+        book::install(book);
+    }
+    if let Ok(Some(path)) = parse_arg::<String>("--endgames", "-E") {
+        let table = EndgameTable::read(open(&path)?)
+            .map_err(|err| format!("Invalid endgame table {path}: {err}"))?;
+        if table.rules() != &rules {
+            return Err(format!(
+                "The endgame table {path} is for other rules: {}",
+                table.rules()
+            ));
+        }
+        endgames::install(table);
+    }
+    #[cfg(feature = "nn")]
+    if let Ok(Some(path)) = parse_arg::<String>("--net", "-N") {
+        let network = nn::Network::read(open(&path)?)
+            .map_err(|err| format!("Invalid network {path}: {err}"))?;
+        if network.size() != rules.size {
+            return Err(format!(
+                "The network {path} is for {0}x{0} fields",
+                network.size()
+            ));
+        }
+        nn::install(network);
+    }
+    if let Ok(Some(path)) = parse_arg::<String>("--weights", "-W") {
+        eval::install(load_weights(&path)?);
+    }
+    Ok(())
+}
 
-        let mut ai_one = SimpleAi::with_seed(Player::PlayerOne, rng.next());
-        let mut ai_two = SimpleAi::with_seed(Player::PlayerTwo, rng.next());
+/// Reads the evaluation weights from the config file at `path`.
+fn load_weights(path: &str) -> Result<Weights, String> {
+    std::fs::read_to_string(path)
+        .map_err(|err| format!("Can't read {path}: {err}"))?
+        .parse()
+        .map_err(|err| format!("Invalid weights {path}: {err}"))
+}
 
-        loop {
-            if base_game.ai_reasoning {
-                game.pp();
-            }
-            if !game.running() {
-                if let Status::Won { winner } = game.status {
-                    if winner == Player::PlayerOne {
-                        ai_one_wins += 1;
-                    } else {
-                        ai_two_wins += 1;
-                    }
-                }
-                turns += game.round() as u64;
-                continue 'outer;
+fn build_book(base_game: &Game, options: &Options) {
+    let (Some("build"), Some(path)) = (args().nth(2).as_deref(), args().nth(3)) else {
+        println!("Usage: book build <file>");
+        return;
+    };
+    let mut values = [BOOK_PLIES, BOOK_DEPTH];
+    for (value, (name, long, short)) in values
+        .iter_mut()
+        .zip([("plies", "--plies", "-l"), ("depth", "--depth", "-d")])
+    {
+        match parse_arg(long, short) {
+            Ok(Some(parsed)) => *value = parsed,
+            Ok(None) => {}
+            Err(value_str) => {
+                println!("Invalid {name}: {value_str}");
+                return;
             }
+        }
+    }
+    let [plies, depth] = values;
+    let seed = options.seed.unwrap_or_default();
+    println!(
+        "Building a book of {plies} plies, searching {depth} moves deep on {} threads",
+        options.threads
+    );
 
-            if game.player() == Player::PlayerOne {
-                game = ai_one.play_iteratively(&mut game);
-            } else {
-                game = ai_two.play_iteratively(&mut game);
-            }
+    let it = Instant::now();
+    let book = Book::build(*base_game.rules(), plies, depth, seed, options.threads);
+    let written =
+        std::fs::File::create(&path).and_then(|file| book.write(std::io::BufWriter::new(file)));
+    if let Err(err) = written {
+        println!("Can't write {path}: {err}");
+        return;
+    }
+    println!(
+        "Wrote {} positions to {path} in {:.1}s",
+        book.len(),
+        it.elapsed().as_secs_f64()
+    );
+}
+
+fn build_endgames(base_game: &Game, options: &Options) {
+    let (Some("build"), Some(path)) = (args().nth(2).as_deref(), args().nth(3)) else {
+        println!("Usage: endgames build <file>");
+        return;
+    };
+    let empty = match parse_arg("--empty", "-e") {
+        Ok(empty) => empty.unwrap_or(ENDGAMES_EMPTY),
+        Err(empty_str) => {
+            println!("Invalid number of empty squares: {empty_str}");
+            return;
+        }
+    };
+    let games = match parse_arg("--games", "-n") {
+        Ok(games) => games.unwrap_or(ENDGAMES_GAMES),
+        Err(games_str) => {
+            println!("Invalid number of games: {games_str}");
+            return;
         }
+    };
+    let seed = options.seed.unwrap_or_default();
+    println!("Solving the endgames with up to {empty} empty squares");
+
+    let it = Instant::now();
+    let table = EndgameTable::build(*base_game.rules(), empty, games, seed);
+    let written =
+        std::fs::File::create(&path).and_then(|file| table.write(std::io::BufWriter::new(file)));
+    if let Err(err) = written {
+        println!("Can't write {path}: {err}");
+        return;
+    }
+    if table.is_complete() {
+        println!("Solved all reachable positions");
+    } else {
+        println!("Too many positions to enumerate, solved those of {games} sampled games");
+    }
+    println!(
+        "Wrote {} positions to {path} in {:.1}s",
+        table.len(),
+        it.elapsed().as_secs_f64()
+    );
+}
+
+fn perft(base_game: &Game, options: &Options) {
+    let Some(Ok(depth)) = args()
+        .skip(2)
+        .find(|arg| !arg.starts_with('-'))
+        .map(|depth| depth.parse::<u8>())
+    else {
+        println!("Perft needs a depth.");
+        return;
+    };
+    let game = match parse_arg::<String>("--position", "-P") {
+        Ok(Some(position)) => {
+            let Ok(game) = parse_notation_with(&position, *base_game.rules()) else {
+                println!("Invalid position: {position}");
+                return;
+            };
+            game
+        }
+        _ => base_game.clone(),
+    };
+    let unique = args().any(|x| x == "--symmetry" || x == "-y");
+    if unique {
+        println!(
+            "{:>5}  {:>14}  {:>9}  {:>12}",
+            "Depth", "Distinct", "Time", "Distinct/s"
+        );
+    } else {
+        println!(
+            "{:>5}  {:>14}  {:>9}  {:>12}",
+            "Depth", "Sequences", "Time", "Moves/s"
+        );
+    }
+
+    for depth in 1..=depth {
+        let it = Instant::now();
+        let count = if unique {
+            perft::perft_unique(&game, depth) as u64
+        } else {
+            perft::perft(&game, depth, options.threads)
+        };
+        let elapsed = it.elapsed().as_secs_f64();
+        #[allow(clippy::cast_precision_loss)]
+        let per_second = count as f64 / elapsed;
+        println!("{depth:>5}  {count:>14}  {elapsed:>8.3}s  {per_second:>12.0}");
     }
+}
 
-    let elapsed = it.elapsed();
+fn eval(base_game: &Game) {
+    let Some(position) = args().skip(2).find(|arg| !arg.starts_with('-')) else {
+        println!("Eval needs a position.");
+        return;
+    };
+    let Ok(game) = parse_notation_with(&position, *base_game.rules()) else {
+        println!("Invalid position: {position}");
+        return;
+    };
+    game.pp();
+    if !game.running() || game.is_initial_move() {
+        println!("Only positions with a piece to place are evaluated.");
+        return;
+    }
+    println!("From the view of {}, who places the piece:", game.player());
+    Features::new(&game).pp(&Weights::installed(), game.field.size());
+}
 
+fn tune(base_game: &Game, options: &Options) {
+    let Some(path) = args().skip(2).find(|arg| !arg.starts_with('-')) else {
+        println!("Tune needs a file to write the weights to.");
+        return;
+    };
+    let mut spsa = TUNE;
+    let (iterations, games, depth) = (
+        parse_arg("--iterations", "-i"),
+        parse_arg("--games", "-n"),
+        parse_arg("--depth", "-d"),
+    );
+    match (iterations, games, depth) {
+        (Ok(iterations), Ok(games), Ok(depth)) => {
+            spsa.iterations = iterations.unwrap_or(spsa.iterations);
+            spsa.games = games.unwrap_or(spsa.games);
+            spsa.depth = depth.unwrap_or(spsa.depth);
+        }
+        (Err(value_str), _, _) | (_, Err(value_str), _) | (_, _, Err(value_str)) => {
+            println!("Invalid value: {value_str}");
+            return;
+        }
+    }
+    let seed = options.seed.unwrap_or_default();
     println!(
-        "Did {} games in {} seconds({:05.3} games/sec)",
-        ITERS,
-        elapsed.as_secs(),
-        ITERS as f64 / (elapsed.as_secs() as f64)
+        "Tuning with seed {seed}: {} iterations of {} games, searching {} moves deep",
+        spsa.iterations, spsa.games, spsa.depth
     );
+
+    let weights = spsa.tune(
+        base_game,
+        Weights::installed(),
+        seed,
+        options.threads,
+        |iteration, score, weights| {
+            let weights = weights.to_string().replace('\n', ", ");
+            println!(
+                "{iteration:>4}: {score:.3} {}",
+                weights.trim_end_matches(", ")
+            );
+        },
+    );
+    if let Err(err) = std::fs::write(&path, weights.to_string()) {
+        println!("Can't write {path}: {err}");
+        return;
+    }
+    println!("Wrote the weights to {path}, load them with --weights.");
+}
+
+#[cfg(feature = "nn")]
+fn train_network(base_game: &Game, options: &Options) {
+    let (Some("train"), Some(path)) = (args().nth(2).as_deref(), args().nth(3)) else {
+        println!("Usage: nn train <file>");
+        return;
+    };
+    let mut training = TRAINING;
+    let (games, epochs, hidden, depth) = (
+        parse_arg("--games", "-n"),
+        parse_arg("--epochs", "-e"),
+        parse_arg("--hidden", "-H"),
+        parse_arg("--depth", "-d"),
+    );
+    match (games, epochs, hidden, depth) {
+        (Ok(games), Ok(epochs), Ok(hidden), Ok(depth)) => {
+            training.games = games.unwrap_or(training.games);
+            training.epochs = epochs.unwrap_or(training.epochs);
+            training.hidden = hidden.unwrap_or(training.hidden);
+            training.depth = depth.unwrap_or(training.depth);
+        }
+        (Err(value_str), ..)
+        | (_, Err(value_str), ..)
+        | (.., Err(value_str), _)
+        | (.., Err(value_str)) => {
+            println!("Invalid value: {value_str}");
+            return;
+        }
+    }
+    let seed = options.seed.unwrap_or_default();
     println!(
-        "Did {} turns in total, average of {} turns per game",
-        turns,
-        turns as f64 / ITERS as f64
+        "Training a network with {} hidden neurons on {} self-play games, for {} epochs",
+        training.hidden, training.games, training.epochs
     );
+
+    let network = nn::train(*base_game.rules(), &training, seed, |epoch, loss| {
+        println!("{epoch:>4}: loss {loss:.4}");
+    });
+    let written =
+        std::fs::File::create(&path).and_then(|file| network.write(std::io::BufWriter::new(file)));
+    if let Err(err) = written {
+        println!("Can't write {path}: {err}");
+        return;
+    }
+    println!("Wrote the network to {path}, load it with --net.");
+}
+
+fn analyze(base_game: &Game, options: &Options) {
+    let Some(path) = args().skip(2).find(|arg| !arg.starts_with('-')) else {
+        println!("Analyze needs a game record.");
+        return;
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) => {
+            println!("Can't read {path}: {err}");
+            return;
+        }
+    };
+    // Records without rules are played with the rules from the command line.
+    let record = match Record::parse(&text, *base_game.rules()) {
+        Ok(record) => record,
+        Err(err) => {
+            println!("Invalid record: {err}");
+            return;
+        }
+    };
+    let depth = match parse_arg("--depth", "-d") {
+        Ok(depth) => depth.unwrap_or(ANALYSIS_DEPTH),
+        Err(depth_str) => {
+            println!("Invalid depth: {depth_str}");
+            return;
+        }
+    };
+    let format = match parse_arg("--format", "-f") {
+        Ok(None | Some(Format::Text)) => Format::Text,
+        Ok(Some(Format::Json)) => Format::Json,
+        _ => {
+            println!("Invalid format, available: text, json");
+            return;
+        }
+    };
+
+    let analysis = analysis::analyze(&record, depth, options.seed.unwrap_or_default());
+    if format == Format::Json {
+        analysis::report_json(&record, &analysis);
+    } else {
+        analysis::report_text(&record, &analysis);
+    }
+}
+
+fn sprt(base_game: &Game, options: &Options) {
+    let strategies: Vec<String> = args().skip(2).filter(|arg| !arg.starts_with('-')).collect();
+    let [a, b] = &strategies[..] else {
+        println!("SPRT needs exactly two strategies.");
+        return;
+    };
+    let (Ok(a), Ok(b)) = (a.parse::<StrategySpec>(), b.parse::<StrategySpec>()) else {
+        println!(
+            "Invalid strategy: {a} or {b}, available: {}",
+            StrategySpec::NAMES
+        );
+        return;
+    };
+
+    let mut sprt = sprt::Sprt {
+        elo0: 0.,
+        elo1: 10.,
+        alpha: 0.05,
+        beta: 0.05,
+    };
+    for (value, (long, short)) in [
+        (&mut sprt.elo0, ("--elo0", "-e0")),
+        (&mut sprt.elo1, ("--elo1", "-e1")),
+        (&mut sprt.alpha, ("--alpha", "-a")),
+        (&mut sprt.beta, ("--beta", "-b")),
+    ] {
+        match parse_arg(long, short) {
+            Ok(Some(parsed)) => *value = parsed,
+            Ok(None) => {}
+            Err(value_str) => {
+                println!("Invalid value for {long}: {value_str}");
+                return;
+            }
+        }
+    }
+    if !(sprt.elo0 < sprt.elo1
+        && 0. < sprt.alpha
+        && sprt.alpha < 1.
+        && 0. < sprt.beta
+        && sprt.beta < 1.)
+    {
+        println!("Invalid SPRT parameters: elo0 needs to be below elo1, alpha and beta in (0, 1).");
+        return;
+    }
+    let max_games = match parse_arg("--games", "-n") {
+        Ok(games) => games.unwrap_or(100_000),
+        Err(games_str) => {
+            println!("Invalid number of games: {games_str}");
+            return;
+        }
+    };
+    let seed = options.seed();
     println!(
-        "Player 1 had {} wins({}%), Player 2 had {} wins({}%).",
-        ai_one_wins,
-        (ai_one_wins as f64 / ITERS as f64) * 100.,
-        ai_two_wins,
-        (ai_two_wins as f64 / ITERS as f64) * 100.
+        "Using seed {seed}, testing H1: {a} is {} Elo stronger than {b}, against H0: {} Elo",
+        sprt.elo1, sprt.elo0
     );
-    let draws = ITERS - ai_one_wins - ai_two_wins;
-    let draw_percentage = (draws as f64 / ITERS as f64) * 100.;
 
-    println!("We had {draws} draws ({draw_percentage}%)");
+    let (score, decision) = sprt::run(
+        base_game,
+        &[a.clone(), b.clone()],
+        &sprt,
+        seed,
+        options.threads,
+        max_games,
+        options.ai_reasoning,
+    );
+    match decision {
+        sprt::Decision::AcceptH1 => println!("H1 accepted: {a} is stronger than {b}."),
+        sprt::Decision::AcceptH0 => println!("H0 accepted: {a} is not stronger than {b}."),
+        sprt::Decision::Continue => println!(
+            "No decision after {} games, the strategies are too close to tell.",
+            score.games()
+        ),
+    }
 }
 
 #[cfg(test)]
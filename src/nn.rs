@@ -0,0 +1,367 @@
+//! A tiny fully-connected neural network that evaluates positions, trained on the outcomes of
+//! self-play games. Only built with the `nn` feature.
+//!
+//! The input of the network is the field, one value for the occupation and one per attribute of
+//! each square, the attributes of the piece in hand, and which pieces remain. A single hidden
+//! layer with rectified linear activations leads to one output, squashed by `tanh`: the expected
+//! outcome for the player on the move, from -1 for a loss to 1 for a win.
+//!
+//! Networks are saved in a binary format: the magic bytes `QNN1`, the size of the field as one
+//! byte, the number of hidden neurons as 2 bytes little endian, and then all weights and biases
+//! as 32-bit little endian floats: those of the hidden layer, neuron by neuron, each followed by
+//! its bias, and those of the output, followed by its bias.
+
+use std::{
+    io::{Read, Write},
+    sync::{Arc, OnceLock},
+};
+
+use crate::{
+    eval::{Evaluator, MAX_EVAL},
+    game::{Game, Player},
+    piece::Piece,
+    rng::{split_seed, RomuDuoJrRand},
+    rules::Rules,
+    search::SearchAi,
+    strategy::Strategy,
+    tt::TranspositionTable,
+};
+
+/// The first bytes of a network file.
+const MAGIC: &[u8; 4] = b"QNN1";
+
+/// The network loaded at startup, if any.
+static INSTALLED: OnceLock<Arc<Network>> = OnceLock::new();
+
+/// A network with one hidden layer, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    /// The size of the field this network evaluates.
+    size: usize,
+    /// The weights of each hidden neuron, followed by its bias.
+    hidden: Vec<Vec<f32>>,
+    /// The weights of the output, one per hidden neuron, followed by its bias.
+    output: Vec<f32>,
+}
+
+impl Network {
+    /// The evaluation of a sure win, as far as the network can tell.
+    const SCALE: f32 = 400.;
+
+    /// Creates a network for fields of `size`, with `hidden` hidden neurons, and small random
+    /// weights.
+    pub fn new(size: usize, hidden: usize, seed: u64) -> Self {
+        let mut rng = RomuDuoJrRand::with_seed(seed);
+        let inputs = input_len(size);
+        #[allow(clippy::cast_precision_loss)]
+        let mut layer = |inputs: usize| {
+            // Glorot-style initialization, with a zero bias.
+            let range = (1. / inputs as f32).sqrt();
+            let mut neuron: Vec<f32> = (0..inputs).map(|_| uniform(&mut rng) * range).collect();
+            neuron.push(0.);
+            neuron
+        };
+        Self {
+            size,
+            hidden: (0..hidden).map(|_| layer(inputs)).collect(),
+            output: layer(hidden),
+        }
+    }
+
+    /// Returns the activations of the hidden layer, and the output for `input`.
+    fn forward(&self, input: &[f32]) -> (Vec<f32>, f32) {
+        let activations: Vec<f32> = self
+            .hidden
+            .iter()
+            .map(|neuron| dot(neuron, input).max(0.))
+            .collect();
+        let output = dot(&self.output, &activations).tanh();
+        (activations, output)
+    }
+
+    /// Returns the expected outcome of `game` for the player on the move, in `-1..=1`.
+    pub fn predict(&self, game: &Game) -> f32 {
+        self.forward(&inputs(game)).1
+    }
+
+    /// Moves the weights a step of `rate` towards predicting `target` for `input`, and returns
+    /// the squared error before the step.
+    fn train_step(&mut self, input: &[f32], target: f32, rate: f32) -> f32 {
+        let (activations, output) = self.forward(input);
+        let error = output - target;
+        // The derivative of the squared error, through the `tanh`.
+        let gradient = 2. * error * (1. - output * output);
+
+        let hidden = self.hidden.len();
+        for (neuron, (&activation, &weight)) in self
+            .hidden
+            .iter_mut()
+            .zip(activations.iter().zip(&self.output))
+        {
+            if activation <= 0. {
+                continue;
+            }
+            let gradient = gradient * weight;
+            for (weight, &input) in neuron.iter_mut().zip(input) {
+                *weight -= rate * gradient * input;
+            }
+            neuron[input.len()] -= rate * gradient;
+        }
+        for (weight, &activation) in self.output.iter_mut().zip(&activations) {
+            *weight -= rate * gradient * activation;
+        }
+        self.output[hidden] -= rate * gradient;
+        error * error
+    }
+
+    /// Writes the network in its binary format, see the [module documentation](self).
+    pub fn write(&self, mut w: impl Write) -> std::io::Result<()> {
+        w.write_all(MAGIC)?;
+        #[allow(clippy::cast_possible_truncation)]
+        w.write_all(&[self.size as u8])?;
+        #[allow(clippy::cast_possible_truncation)]
+        w.write_all(&(self.hidden.len() as u16).to_le_bytes())?;
+        for weight in self.hidden.iter().flatten().chain(&self.output) {
+            w.write_all(&weight.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a network written by [`Network::write`].
+    pub fn read(mut r: impl Read) -> Result<Self, String> {
+        let mut read = |len: usize| {
+            let mut buf = vec![0; len];
+            r.read_exact(&mut buf)
+                .map(|()| buf)
+                .map_err(|err| format!("Truncated network: {err}"))
+        };
+        if read(MAGIC.len())? != MAGIC {
+            return Err("Not a network".to_string());
+        }
+        let size = usize::from(read(1)?[0]);
+        if !(3..=Piece::MAX_ATTRIBUTES).contains(&size) {
+            return Err(format!("Invalid size: {size}"));
+        }
+        let hidden = usize::from(u16::from_le_bytes(read(2)?.try_into().unwrap()));
+        let mut layer = |inputs: usize| -> Result<Vec<f32>, String> {
+            Ok(read(4 * (inputs + 1))?
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect())
+        };
+        // Neurons are stored as they are read, so a corrupt count fails at the end of the file.
+        let mut neurons = vec![];
+        for _ in 0..hidden {
+            neurons.push(layer(input_len(size))?);
+        }
+        Ok(Self {
+            size,
+            hidden: neurons,
+            output: layer(hidden)?,
+        })
+    }
+
+    /// The size of the field this network evaluates.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Evaluator for Network {
+    fn evaluate(&self, game: &Game) -> i16 {
+        #[allow(clippy::cast_possible_truncation)]
+        let score = (self.predict(game) * Self::SCALE).round() as i16;
+        score.clamp(-MAX_EVAL, MAX_EVAL)
+    }
+}
+
+/// The parameters of [`train`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Training {
+    /// The number of self-play games to learn from.
+    pub games: usize,
+    /// How many moves deep the search AIs of the self-play games search.
+    pub depth: u8,
+    /// The number of passes over all positions of the games.
+    pub epochs: usize,
+    pub hidden: usize,
+    pub learning_rate: f32,
+}
+
+/// Plays self-play games between [`SearchAi`]s with `rules`, and trains a new network to
+/// predict their outcomes. Calls `report` with the mean squared error of each epoch.
+/// The network only depends on the `seed`.
+pub fn train(
+    rules: Rules,
+    training: &Training,
+    seed: u64,
+    mut report: impl FnMut(usize, f32),
+) -> Network {
+    let mut rng = RomuDuoJrRand::with_seed(seed);
+    let mut network = Network::new(rules.size, training.hidden, rng.next());
+
+    let mut samples = vec![];
+    for i in 0..training.games {
+        samples.extend(self_play(rules, training.depth, split_seed(seed, i as u64)));
+    }
+
+    for epoch in 0..training.epochs {
+        rng.shuffle(&mut samples);
+        let mut loss = 0.;
+        for (input, target) in &samples {
+            loss += network.train_step(input, *target, training.learning_rate);
+        }
+        #[allow(clippy::cast_precision_loss)]
+        report(epoch + 1, loss / samples.len().max(1) as f32);
+    }
+    network
+}
+
+/// Plays a game between two [`SearchAi`]s, and returns the inputs of each position with a
+/// piece to place, with the outcome for the player on the move.
+fn self_play(rules: Rules, depth: u8, seed: u64) -> Vec<(Vec<f32>, f32)> {
+    const TT_MB: usize = 1;
+
+    let mut rng = RomuDuoJrRand::with_seed(seed);
+    let mut ais = [(); 2].map(|()| {
+        SearchAi::new(
+            depth,
+            rng.next(),
+            Arc::new(TranspositionTable::with_size_mb(TT_MB)),
+        )
+    });
+    let mut game = Game::new(Player::PlayerOne, rules);
+    let mut positions = vec![];
+    while game.running() {
+        if !game.is_initial_move() {
+            positions.push((inputs(&game), game.player()));
+        }
+        let ai = match game.player() {
+            Player::PlayerOne => &mut ais[0],
+            Player::PlayerTwo => &mut ais[1],
+        };
+        game = ai.play(&game);
+    }
+
+    let winner = game.winner();
+    positions
+        .into_iter()
+        .map(|(input, player)| {
+            let target = match winner {
+                Some(winner) if winner == player => 1.,
+                Some(_) => -1.,
+                None => 0.,
+            };
+            (input, target)
+        })
+        .collect()
+}
+
+/// The number of inputs of a network for fields of `size`.
+fn input_len(size: usize) -> usize {
+    size * size * (size + 1) + size + (1 << size)
+}
+
+/// Encodes `game` as the input of a network, see the [module documentation](self).
+fn inputs(game: &Game) -> Vec<f32> {
+    let size = game.field.size();
+    let attributes = |piece: Piece, input: &mut Vec<f32>| {
+        for attribute in 0..size {
+            input.push(if piece.props() >> attribute & 1 == 1 {
+                1.
+            } else {
+                -1.
+            });
+        }
+    };
+
+    let mut input = Vec::with_capacity(input_len(size));
+    for pos in game.field.all_positions() {
+        match game.field.get(pos) {
+            Some(piece) => {
+                input.push(1.);
+                attributes(piece, &mut input);
+            }
+            None => input.extend(std::iter::repeat_n(0., size + 1)),
+        }
+    }
+    match game.next_piece() {
+        Some(piece) => attributes(piece, &mut input),
+        None => input.extend(std::iter::repeat_n(0., size)),
+    }
+    let mut remaining = vec![0.; 1 << size];
+    for piece in game.remaining_pieces() {
+        remaining[usize::from(piece.props())] = 1.;
+    }
+    input.extend(remaining);
+    input
+}
+
+/// A random number in `-1..1`.
+#[allow(clippy::cast_precision_loss)]
+fn uniform(rng: &mut RomuDuoJrRand) -> f32 {
+    (rng.next() >> 40) as f32 / (1 << 23) as f32 - 1.
+}
+
+fn dot(weights: &[f32], input: &[f32]) -> f32 {
+    let (bias, weights) = weights.split_last().expect("Neurons have a bias");
+    weights.iter().zip(input).map(|(w, x)| w * x).sum::<f32>() + bias
+}
+
+/// Makes `network` the network of all `nn` strategies.
+pub fn install(network: Network) {
+    let _ = INSTALLED.set(Arc::new(network));
+}
+
+/// The network loaded at startup, if any.
+pub fn installed() -> Option<Arc<Network>> {
+    INSTALLED.get().cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        game::{Game, Player},
+        nn::{input_len, inputs, train, Network, Training},
+        notation::parse_notation,
+        rules::Rules,
+    };
+
+    #[test]
+    fn test_inputs() {
+        let game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        assert_eq!(inputs(&game).len(), input_len(4));
+        let game = parse_notation("e8c./..../..../.... 3 2").unwrap();
+        let input = inputs(&game);
+        assert_eq!(input.len(), 100);
+        // The first square holds e: occupied, and without attribute 1.
+        assert_eq!(input[..5], [1., -1., 1., 1., 1.]);
+        // 12 pieces remain.
+        assert_eq!(input[84..].iter().filter(|&&x| x > 0.).count(), 12);
+    }
+
+    #[test]
+    fn test_train() {
+        let training = Training {
+            games: 4,
+            depth: 1,
+            epochs: 20,
+            hidden: 8,
+            learning_rate: 0.01,
+        };
+        let mut losses = vec![];
+        let network = train(Rules::CLASSIC, &training, 1, |_, loss| losses.push(loss));
+        assert!(losses[19] < losses[0], "{losses:?}");
+        assert_eq!(network, train(Rules::CLASSIC, &training, 1, |_, _| {}));
+
+        let mut bytes = vec![];
+        network.write(&mut bytes).unwrap();
+        assert_eq!(Network::read(&bytes[..]).unwrap(), network);
+        assert!(Network::read(&bytes[..bytes.len() - 1]).is_err());
+        // The number of hidden neurons follows the magic bytes and the size.
+        bytes[5..7].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(Network::read(&bytes[..])
+            .unwrap_err()
+            .starts_with("Truncated network"));
+    }
+}
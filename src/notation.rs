@@ -0,0 +1,300 @@
+//! A compact text notation for quarto positions, similar to FEN in chess.
+//!
+//! A position is written as `<field> <next piece> <player> [<rules>] [clock=<clock>]`,
+//! e.g. `9.../.0../..../.... f 1`:
+//!  * `field`: the rows of the field, separated by `/`. Each square is `.` if it is empty, or
+//!    the properties of the piece on it, as a digit in [`DIGITS`]. These are hex digits for
+//!    the classic 4x4 field. The number of rows determines the size of the field.
+//!  * `next piece`: the piece to place next, as a digit, or `-` if there is none.
+//!  * `player`: `1` or `2`, the player to move, or, if the game is over, the winner, or the
+//!    player that moved last for a draw. It's followed by `!` if the player to move can claim a
+//!    win that the previous player missed to call, see [`Rules::call_quarto`].
+//!  * `rules`: the [`Rules`], as long as they differ from the classic rules in more than the size.
+//!  * `clock`: the [`Clock`], if the game is played with a time control.
+
+use crate::{
+    clock::Clock,
+    field::Field,
+    game::{Game, Player},
+    piece::Piece,
+    rules::Rules,
+};
+
+/// The digits for the properties of the pieces, up to [`Piece::MAX_ATTRIBUTES`] attributes.
+const DIGITS: &[u8; 1 << Piece::MAX_ATTRIBUTES] =
+    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ+*";
+
+/// Writes the notation of the current position of a game.
+pub fn to_notation(game: &Game) -> String {
+    let size = game.field.size();
+    let mut ret = String::with_capacity(size * (size + 1) + 4);
+    for y in 0..size {
+        if y > 0 {
+            ret.push('/');
+        }
+        for x in 0..size {
+            ret.push(piece_char(game.field.get((x, y))));
+        }
+    }
+    ret.push(' ');
+    ret.push(
+        game.next_piece()
+            .map_or('-', |piece| piece_char(Some(piece))),
+    );
+    ret.push(' ');
+    ret.push(match game.player() {
+        Player::PlayerOne => '1',
+        Player::PlayerTwo => '2',
+    });
+    if game.is_claimable() {
+        ret.push('!');
+    }
+    let rules = *game.rules();
+    if rules
+        != (Rules {
+            size,
+            ..Rules::CLASSIC
+        })
+    {
+        ret.push(' ');
+        ret.push_str(&rules.to_string());
+    }
+    if let Some(clock) = game.clock {
+        ret.push_str(" clock=");
+        ret.push_str(&clock.to_string());
+    }
+    ret
+}
+
+/// Parses a position in notation to a new game.
+#[cfg(test)]
+pub fn parse_notation(s: &str) -> Result<Game, ()> {
+    parse_notation_with(s, Rules::CLASSIC)
+}
+
+/// Parses a position in notation to a new game, played with `default_rules` unless the notation
+/// has its own. The size of the field always follows the notation.
+pub fn parse_notation_with(s: &str, default_rules: Rules) -> Result<Game, ()> {
+    let parts: Vec<&str> = s.split_whitespace().collect();
+    let (rows, next_piece, player, rules, clock) = match parts[..] {
+        [rows, next_piece, player] => (rows, next_piece, player, None, None),
+        [rows, next_piece, player, extra] => match extra.strip_prefix("clock=") {
+            Some(clock) => (rows, next_piece, player, None, Some(clock)),
+            None => (rows, next_piece, player, Some(extra), None),
+        },
+        [rows, next_piece, player, rules, clock] => (
+            rows,
+            next_piece,
+            player,
+            Some(rules),
+            Some(clock.strip_prefix("clock=").ok_or(())?),
+        ),
+        _ => return Err(()),
+    };
+    let clock: Option<Clock> = clock.map(str::parse).transpose()?;
+
+    let rows: Vec<&str> = rows.split('/').collect();
+    let size = rows.len();
+    let rules = match rules {
+        Some(rules) => rules.parse().map_err(|_| ())?,
+        None => Rules {
+            size,
+            ..default_rules
+        },
+    };
+    if rules.size != size || rules.validate().is_err() {
+        return Err(());
+    }
+    let mut field = Field::with_rules(&rules);
+    for (y, row) in rows.iter().enumerate() {
+        if row.chars().count() != size {
+            return Err(());
+        }
+        for (x, c) in row.chars().enumerate() {
+            if let Some(piece) = parse_piece(c, size)? {
+                field.put((x, y), piece)?;
+            }
+        }
+    }
+
+    let next_piece = if next_piece == "-" {
+        None
+    } else {
+        let mut chars = next_piece.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(parse_piece(c, size)?.ok_or(())?),
+            _ => return Err(()),
+        }
+    };
+
+    let (player, claimable) = match player.strip_suffix('!') {
+        Some(player) => (player, true),
+        None => (player, false),
+    };
+    let player = match player {
+        "1" => Player::PlayerOne,
+        "2" => Player::PlayerTwo,
+        _ => return Err(()),
+    };
+
+    Game::from_position(rules, field, next_piece, player, claimable, clock)
+}
+
+pub fn piece_char(piece: Option<Piece>) -> char {
+    piece.map_or('.', |piece| char::from(DIGITS[usize::from(piece.props())]))
+}
+
+/// Parses a square, for a field of `size` x `size`.
+pub fn parse_piece(c: char, size: usize) -> Result<Option<Piece>, ()> {
+    if c == '.' {
+        return Ok(None);
+    }
+    let props = DIGITS
+        .iter()
+        .take(1 << size)
+        .position(|&digit| char::from(digit) == c)
+        .ok_or(())?;
+    #[allow(clippy::cast_possible_truncation)]
+    Ok(Some(Piece::with_attributes(props as u8, size)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        game::{Game, Player, Status},
+        notation::{parse_notation, parse_notation_with, to_notation},
+        piece::Piece,
+        rules::Rules,
+    };
+
+    #[test]
+    fn test_roundtrip() {
+        let mut game = Game::new(Player::PlayerTwo, Rules::CLASSIC);
+        assert_eq!(to_notation(&game), "..../..../..../.... - 2");
+
+        game.initial_move(Piece::with_props(9)).unwrap();
+        game.do_move((0, 0), Piece::with_props(0)).unwrap();
+        game.do_move((1, 1), Piece::with_props(15)).unwrap();
+        let notation = to_notation(&game);
+        assert_eq!(notation, "9.../.0../..../.... f 1");
+
+        let parsed = parse_notation(&notation).unwrap();
+        assert_eq!(parsed.field, game.field);
+        assert_eq!(parsed.status, game.status);
+        assert_eq!(parsed.remaining_pieces(), game.remaining_pieces());
+    }
+
+    #[test]
+    fn test_rules() {
+        let rules: Rules = "lines=rows+squares,draw=last-player-wins".parse().unwrap();
+        let mut game = Game::new(Player::PlayerOne, rules);
+        game.initial_move(Piece::with_props(3)).unwrap();
+        let notation = to_notation(&game);
+        assert_eq!(
+            notation,
+            "..../..../..../.... 3 2 size=4,lines=rows+squares,draw=last-player-wins"
+        );
+        assert_eq!(parse_notation(&notation).unwrap().rules(), &rules);
+
+        // Without rules in the notation, the default rules are used.
+        let game = parse_notation_with("..../..../..../.... 3 2", rules).unwrap();
+        assert_eq!(game.rules(), &rules);
+        assert!(parse_notation("..../..../..../.... 3 2 size=5").is_err());
+    }
+
+    #[test]
+    fn test_claimable() {
+        let notation =
+            "0123/..../..../.... 4 2! size=4,lines=rows+diagonals,draw=draw,variants=call-quarto";
+        let game = parse_notation(notation).unwrap();
+        assert!(game.is_claimable());
+        assert_eq!(to_notation(&game), notation);
+
+        // Without the call-quarto rule, the line wins right away.
+        assert!(parse_notation("0123/..../..../.... 4 2!").is_err());
+    }
+
+    #[test]
+    fn test_clock() {
+        let notation = "9.../.0../..../.... f 1 clock=5+3/287.5/300";
+        let game = parse_notation(notation).unwrap();
+        assert_eq!(game.clock.unwrap().to_string(), "5+3/287.5/300");
+        assert_eq!(to_notation(&game), notation);
+
+        // Player two ran out of time, so player one won.
+        let notation = "9.../.0../..../.... f 1 size=4,lines=rows,draw=draw clock=1+0/60/0";
+        let game = parse_notation(notation).unwrap();
+        assert!(game.clock.unwrap().flagged(Player::PlayerTwo));
+        assert_eq!(game.winner(), Some(Player::PlayerOne));
+        assert_eq!(
+            to_notation(&game),
+            "9.../.0../..../.... - 1 size=4,lines=rows,draw=draw clock=1+0/60/0"
+        );
+        assert!(parse_notation("9.../.0../..../.... f 1 clock=1+0/0/0").is_err());
+
+        assert!(parse_notation("9.../.0../..../.... f 1 clock=5+3").is_err());
+        assert!(parse_notation("9.../.0../..../.... f 1 clock=5+3/1/1 size=4").is_err());
+    }
+
+    #[test]
+    fn test_lost_on_time() {
+        let mut game = parse_notation("9.../.0../..../.... f 2 clock=1+0/60/60").unwrap();
+        game.charge_clock(Player::PlayerTwo, Duration::from_mins(2));
+        assert_eq!(game.winner(), Some(Player::PlayerOne));
+
+        let notation = to_notation(&game);
+        assert_eq!(notation, "9.../.0../..../.... - 1 clock=1+0/60/0");
+        let parsed = parse_notation(&notation).unwrap();
+        assert_eq!(parsed.status, game.status);
+        assert_eq!(parsed.clock, game.clock);
+        assert_eq!(to_notation(&parsed), notation);
+    }
+
+    #[test]
+    fn test_parse_finished() {
+        let game = parse_notation("0123/..../..../.... - 1").unwrap();
+        assert_eq!(
+            game.status,
+            Status::Won {
+                winner: Player::PlayerOne
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse_notation("").is_err());
+        assert!(parse_notation("..../..../..../... - 1").is_err());
+        assert!(parse_notation("..../..../..../.... - 3").is_err());
+        // The same piece twice.
+        assert!(parse_notation("1.../..../..../.... 1 1").is_err());
+        // Pieces on the field, but nothing to place.
+        assert!(parse_notation("1.../..../..../.... - 1").is_err());
+        // Pieces with more attributes than the field has rows.
+        assert!(parse_notation("g.../..../..../.... 1 1").is_err());
+    }
+
+    #[test]
+    fn test_larger_field() {
+        let mut game = Game::new(
+            Player::PlayerOne,
+            Rules {
+                size: 5,
+                ..Rules::CLASSIC
+            },
+        );
+        assert_eq!(to_notation(&game), "...../...../...../...../..... - 1");
+
+        game.initial_move(Piece::with_attributes(31, 5)).unwrap();
+        game.do_move((4, 4), Piece::with_attributes(16, 5)).unwrap();
+        let notation = to_notation(&game);
+        assert_eq!(notation, "...../...../...../...../....v g 1");
+
+        let parsed = parse_notation(&notation).unwrap();
+        assert_eq!(parsed.field, game.field);
+        assert_eq!(parsed.status, game.status);
+        assert_eq!(parsed.remaining_pieces(), game.remaining_pieces());
+    }
+}
@@ -0,0 +1,101 @@
+//! Counts the nodes of the game tree, as chess engines do to validate their move generation.
+//!
+//! [`perft`] counts the legal move sequences of a given length from a position, and
+//! [`perft_unique`] the distinct positions at the end of them, up to symmetry. Games that end
+//! early are not extended.
+
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::game::{Game, Move};
+
+/// Counts the sequences of `depth` legal moves from `game`, on `threads` threads.
+pub fn perft(game: &Game, depth: u8, threads: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = game.legal_moves();
+    let next = AtomicUsize::new(0);
+    let count = AtomicU64::new(0);
+    thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            s.spawn(|| {
+                while let Some(&mv) = moves.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    count.fetch_add(perft_move(game, mv, depth - 1), Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    count.into_inner()
+}
+
+/// Counts the sequences of `depth` legal moves after playing `mv` in `game`.
+fn perft_move(game: &Game, mv: Move, depth: u8) -> u64 {
+    let mut child = game.clone();
+    child.play_move(mv).expect("Only legal moves are generated");
+    if depth == 0 {
+        return 1;
+    }
+    child
+        .legal_moves()
+        .into_iter()
+        .map(|mv| perft_move(&child, mv, depth - 1))
+        .sum()
+}
+
+/// Counts the distinct positions, up to symmetry, at the end of the sequences of `depth` legal
+/// moves from `game`.
+pub fn perft_unique(game: &Game, depth: u8) -> usize {
+    let mut level = vec![game.clone()];
+    for _ in 0..depth {
+        let mut visited = HashSet::new();
+        level = level
+            .iter()
+            .flat_map(|game| {
+                game.legal_moves().into_iter().map(|mv| {
+                    let mut child = game.clone();
+                    child.play_move(mv).expect("Only legal moves are generated");
+                    child
+                })
+            })
+            .filter(|child| visited.insert(child.canonical_key()))
+            .collect();
+    }
+    level.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        game::{Game, Player},
+        notation::parse_notation,
+        perft::{perft, perft_unique},
+        rules::Rules,
+    };
+
+    #[test]
+    fn test_perft() {
+        let game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        assert_eq!(perft(&game, 0, 1), 1);
+        assert_eq!(perft(&game, 1, 1), 16);
+        assert_eq!(perft(&game, 2, 1), 16 * 16 * 15);
+        assert_eq!(perft(&game, 3, 2), 806_400);
+
+        // 3 completes the top row, so the 12 moves that place it on 3,0 end the game.
+        let game = parse_notation("012./..../..../.... 3 1").unwrap();
+        assert_eq!(perft(&game, 1, 1), 13 * 12);
+        assert_eq!(perft(&game, 2, 1), 12 * 12 * 12 * 11);
+    }
+
+    #[test]
+    fn test_perft_unique() {
+        let game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        assert_eq!(perft_unique(&game, 1), 1);
+        // On the classic field, the corners are equivalent to the inner squares, but not to the
+        // edges. The piece to place next shares 0, 1, 2 or 3 attributes with the placed one.
+        assert_eq!(perft_unique(&game, 2), 2 * 4);
+    }
+}
@@ -3,7 +3,8 @@ use std::fmt::Formatter;
 /// A quarto piece.
 #[derive(Default, PartialEq, Eq, Copy, Clone, Hash, Ord, PartialOrd)]
 pub struct Piece {
-    pub properties: u8,
+    /// The set attributes in the low bits, followed by their complement in the next bits.
+    pub properties: u16,
 }
 
 impl std::fmt::Debug for Piece {
@@ -21,45 +22,97 @@ pub enum Property {
     Light = 1 << 3,
 }
 
+/// How the attributes beyond the four [`Property`]s are rendered, when they are set and unset.
+const EXTRA_ATTRIBUTES: [(&str, &str); Piece::MAX_ATTRIBUTES - 4] = [("⭐", "🌙"), ("🔔", "🔕")];
+
 impl Piece {
+    /// The number of attributes of the pieces of the classic game.
+    #[cfg(test)]
+    pub const ATTRIBUTES: usize = 4;
+    /// The most attributes a piece can have.
+    pub const MAX_ATTRIBUTES: usize = 6;
+
+    /// A piece of the classic game, with 4 attributes.
+    #[cfg(test)]
     #[must_use]
     pub const fn with_props(props: u8) -> Self {
-        assert!(props >> 4 == 0, "top bits should be clear");
-        let props = props | ((!props) << 4);
+        Self::with_attributes(props, Self::ATTRIBUTES)
+    }
 
-        assert!(props >> 4 & props == 0);
+    /// A piece with `attributes` binary attributes, of which `props` are set.
+    #[must_use]
+    pub const fn with_attributes(props: u8, attributes: usize) -> Self {
+        assert!(attributes <= Self::MAX_ATTRIBUTES);
+        assert!(props >> attributes == 0, "top bits should be clear");
+        let mask = (1 << attributes) - 1;
+        let props = props as u16;
+        let props = props | ((!props & mask) << attributes);
+
+        assert!(props >> attributes & props == 0);
         Piece { properties: props }
     }
 
+    /// The number of attributes of this piece.
+    #[must_use]
+    pub fn attributes(self) -> usize {
+        self.properties.count_ones() as usize
+    }
+
+    /// Returns the set properties, without their complements in the top bits.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn props(self) -> u8 {
+        (self.properties & ((1 << self.attributes()) - 1)) as u8
+    }
+
     pub fn get(self, prop: Property) -> bool {
-        (self.properties & prop as u8) != 0
+        (self.properties & prop as u16) != 0
+    }
+
+    /// The glyphs of the attributes beyond the four [`Property`]s.
+    fn extra_attributes(self) -> impl Iterator<Item = &'static str> {
+        let props = self.props();
+        EXTRA_ATTRIBUTES
+            .iter()
+            .take(self.attributes().saturating_sub(4))
+            .enumerate()
+            .map(move |(i, &(set, unset))| {
+                if props & (1 << (i + 4)) != 0 {
+                    set
+                } else {
+                    unset
+                }
+            })
+    }
+
+    /// The glyph of `prop`, depending on whether it is set, or `None` if this piece has fewer
+    /// attributes than that.
+    fn glyph(self, prop: Property) -> Option<&'static str> {
+        let (set, unset) = match prop {
+            Property::Tall => ("✋", "🤏"),
+            Property::Round => ("🟠", "🔶"),
+            Property::Full => ("🔴", "⭕"),
+            Property::Light => ("⬜", "⬛"),
+        };
+        // On pieces with fewer attributes, the bit holds the complement of another attribute.
+        let index = (prop as u8).trailing_zeros() as usize;
+        (index < self.attributes()).then(|| if self.get(prop) { set } else { unset })
     }
 
     pub fn pp_write(self, f: &mut Formatter) -> std::fmt::Result {
         f.write_str("[")?;
-        if self.get(Property::Tall) {
-            f.write_str("✋")?;
-            //f.write_str("⬆️")?;
-        } else {
-            f.write_str("🤏")?;
-            //f.write_str("⬇️")?;
-        }
-        if self.get(Property::Round) {
-            f.write_str("🟠")?;
-        } else {
-            write!(f, "🔶")?;
+        for prop in [
+            Property::Tall,
+            Property::Round,
+            Property::Full,
+            Property::Light,
+        ] {
+            if let Some(glyph) = self.glyph(prop) {
+                f.write_str(glyph)?;
+            }
         }
-        if self.get(Property::Full) {
-            f.write_str("🔴")?;
-        } else {
-            f.write_str("⭕")?;
-        }
-        if self.get(Property::Light) {
-            //f.write_str("🏳️")?;
-            f.write_str("⬜")?;
-        } else {
-            //f.write_str("🏴")?;
-            f.write_str("⬛")?;
+        for glyph in self.extra_attributes() {
+            f.write_str(glyph)?;
         }
         f.write_str("]")
     }
@@ -68,29 +121,18 @@ impl Piece {
     pub fn pp(self) {
         // TODO: This could probably be in Debug or Display?
         print!("[");
-        if self.get(Property::Tall) {
-            print!("✋");
-            //print!("️⬆️");
-        } else {
-            print!("🤏");
-            //print!("⬇️");
-        }
-        if self.get(Property::Light) {
-            //print!("🏳️");
-            print!("⬜");
-        } else {
-            //print!("🏴");
-            print!("⬛");
-        }
-        if self.get(Property::Round) {
-            print!("🟠");
-        } else {
-            print!("🔶");
+        for prop in [
+            Property::Tall,
+            Property::Light,
+            Property::Round,
+            Property::Full,
+        ] {
+            if let Some(glyph) = self.glyph(prop) {
+                print!("{glyph}");
+            }
         }
-        if self.get(Property::Full) {
-            print!("🔴");
-        } else {
-            print!("⭕");
+        for glyph in self.extra_attributes() {
+            print!("{glyph}");
         }
         print!("]");
     }
@@ -108,4 +150,25 @@ mod tests {
         // Test the binary representation of a piece.
         assert!(TEST_LIGHT_TALL.properties == 0b_0110_1001);
     }
+
+    #[test]
+    fn test_more_attributes() {
+        let piece = Piece::with_attributes(0b1_0001, 5);
+        assert_eq!(piece.properties, 0b_01110_10001);
+        assert_eq!(piece.attributes(), 5);
+        assert_eq!(piece.props(), 0b1_0001);
+        assert!(piece.get(Property::Tall));
+        assert!(!piece.get(Property::Light));
+        assert_eq!(format!("{piece:?}"), "[✋🔶⭕⬛⭐]");
+    }
+
+    #[test]
+    fn test_fewer_attributes() {
+        // The fourth bit is the complement of Tall here, and not an attribute of its own.
+        let piece = Piece::with_attributes(0b101, 3);
+        assert_eq!(piece.attributes(), 3);
+        assert_eq!(format!("{piece:?}"), "[✋🔶🔴]");
+        let piece = Piece::with_attributes(0b010, 3);
+        assert_eq!(format!("{piece:?}"), "[🤏🟠⭕]");
+    }
 }
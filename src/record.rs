@@ -0,0 +1,261 @@
+//! Game records: the position a game started from, and the moves played from there.
+//!
+//! A record is written as the start position in [`crate::notation`] on the first line, followed
+//! by the moves, separated by whitespace, e.g.
+//! ```text
+//! ..../..../..../.... - 1
+//! 9 0,0:0 1,1:f
+//! ```
+//! Positions count from 0, and pieces are written as in the position notation. A move is:
+//!  * `<piece>`: the initial move, giving `piece` to the opponent.
+//!  * `<x>,<y>:<piece>`: placing the piece on `x,y`, and giving `piece` to the opponent. On the
+//!    last move, there is nothing left to give, and `piece` is the placed piece again.
+//!  * `<x>,<y>!`: placing the piece on `x,y`, and calling "Quarto!".
+//!  * `claim`: claiming a win that the opponent missed to call.
+//!
+//! In games with a clock, each move is followed by `@<seconds>`, the time the player had left
+//! after it, e.g. `0,0:0@287.5`. A player with no time left lost on time.
+
+use core::fmt::Display;
+use std::time::Duration;
+
+use crate::{
+    clock::{duration_value, parse_duration},
+    field::try_parse_pos,
+    game::{Game, Move, Player},
+    notation::{parse_notation_with, parse_piece, piece_char, to_notation},
+    rules::Rules,
+};
+
+/// A game, as a start position and the moves played from it.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub start: Game,
+    pub moves: Vec<Move>,
+    /// The time each player had left after their move, in games with a clock, or else empty.
+    pub times: Vec<Duration>,
+}
+
+impl Record {
+    /// Parses a record, see the [module documentation](self). The start position is played with
+    /// `default_rules`, unless it has its own.
+    /// Fails with the line of the start position, or the move that is invalid or illegal.
+    pub fn parse(s: &str, default_rules: Rules) -> Result<Self, String> {
+        let mut lines = s.lines().filter(|line| !line.trim().is_empty());
+        let start = lines.next().ok_or("The record is empty")?;
+        let start = parse_notation_with(start, default_rules)
+            .map_err(|()| format!("Invalid start position: {start}"))?;
+
+        let mut game = start.clone();
+        let mut moves = vec![];
+        let mut times = vec![];
+        for token in lines.flat_map(str::split_whitespace) {
+            let invalid = || format!("Invalid move {}: {token}", moves.len() + 1);
+            let (mv_str, time) = match token.split_once('@') {
+                Some((mv_str, time)) if start.clock.is_some() => (
+                    mv_str,
+                    Some(parse_duration(time, 1.).map_err(|()| invalid())?),
+                ),
+                Some(_) => return Err(format!("Time without a clock: {token}")),
+                None => (token, None),
+            };
+            let mv = parse_move(mv_str, game.field.size())
+                .filter(|&mv| game.play_move(mv).is_ok())
+                .ok_or_else(invalid)?;
+            moves.push(mv);
+            times.extend(time);
+            if !times.is_empty() && times.len() != moves.len() {
+                return Err(format!(
+                    "Missing time of move {}",
+                    times.len().max(moves.len())
+                ));
+            }
+        }
+        Ok(Self {
+            start,
+            moves,
+            times,
+        })
+    }
+
+    /// Starts a record of a game played from `start`.
+    pub fn new(start: Game) -> Self {
+        Self {
+            start,
+            moves: vec![],
+            times: vec![],
+        }
+    }
+
+    /// Adds `mv`, played in a game that is `after` it, with the time `player` has left then.
+    pub fn push(&mut self, mv: Move, player: Player, after: &Game) {
+        self.moves.push(mv);
+        if let Some(clock) = after.clock {
+            self.times.push(clock.remaining(player));
+        }
+    }
+
+    /// Drops all but the first `len` moves.
+    pub fn truncate(&mut self, len: usize) {
+        self.moves.truncate(len);
+        self.times.truncate(len);
+    }
+
+    /// Replays the game, and returns the start position and the position after each move, with
+    /// the clocks as they were.
+    pub fn positions(&self) -> Vec<Game> {
+        let mut game = self.start.clone();
+        let mut positions = vec![game.clone()];
+        for (i, &mv) in self.moves.iter().enumerate() {
+            let player = game.player();
+            game.play_move(mv).expect("Records only hold legal moves");
+            if let Some(&left) = self.times.get(i) {
+                game.set_time_left(player, left);
+            }
+            positions.push(game.clone());
+        }
+        positions
+    }
+
+    /// Returns the move that leads from `before` to `after`, if there is one.
+    pub fn find_move(before: &Game, after: &Game) -> Option<Move> {
+        before.legal_moves().into_iter().find(|&mv| {
+            let mut game = before.clone();
+            game.play_move(mv).is_ok() && game.field == after.field && game.status == after.status
+        })
+    }
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", to_notation(&self.start))?;
+        let moves: Vec<String> = self
+            .moves
+            .iter()
+            .enumerate()
+            .map(|(i, &mv)| match self.times.get(i) {
+                Some(&left) => format!("{}@{}", move_to_notation(mv), duration_value(left, 1.)),
+                None => move_to_notation(mv),
+            })
+            .collect();
+        write!(f, "{}", moves.join(" "))
+    }
+}
+
+/// Writes a move, see the [module documentation](self).
+pub fn move_to_notation(mv: Move) -> String {
+    match mv {
+        Move::Initial { next_piece } => piece_char(Some(next_piece)).to_string(),
+        Move::Place {
+            pos: (x, y),
+            next_piece,
+        } => format!("{x},{y}:{}", piece_char(Some(next_piece))),
+        Move::Quarto { pos: (x, y) } => format!("{x},{y}!"),
+        Move::Claim => "claim".to_string(),
+    }
+}
+
+/// Parses a move on a field of `size` x `size`, see the [module documentation](self).
+/// The move is not checked to be legal.
+pub fn parse_move(s: &str, size: usize) -> Option<Move> {
+    let parse_piece = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => parse_piece(c, size).ok().flatten(),
+            _ => None,
+        }
+    };
+    if s == "claim" {
+        Some(Move::Claim)
+    } else if let Some(pos) = s.strip_suffix('!') {
+        Some(Move::Quarto {
+            pos: try_parse_pos(pos).ok()?,
+        })
+    } else if let Some((pos, piece)) = s.split_once(':') {
+        Some(Move::Place {
+            pos: try_parse_pos(pos).ok()?,
+            next_piece: parse_piece(piece)?,
+        })
+    } else {
+        Some(Move::Initial {
+            next_piece: parse_piece(s)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        ai::RandomAi,
+        game::{Game, Player},
+        record::Record,
+        rules::Rules,
+        strategy::Strategy,
+    };
+
+    #[test]
+    fn test_roundtrip() {
+        let text = "..../..../..../.... - 1\n9 0,0:0 1,1:f";
+        let record = Record::parse(text, Rules::CLASSIC).unwrap();
+        assert_eq!(record.moves.len(), 3);
+        assert_eq!(record.to_string(), text);
+        let positions = record.positions();
+        assert_eq!(positions.len(), 4);
+        assert_eq!(positions[0].player(), Player::PlayerOne);
+
+        let record = Record::parse(
+            "..../..../..../.... - 1 variants=call-quarto\n9\n0,0:0 claim",
+            Rules::CLASSIC,
+        );
+        assert_eq!(record.unwrap_err(), "Invalid move 3: claim");
+        assert!(Record::parse("..../..../..../.... - 1\n9 0,0:9", Rules::CLASSIC).is_err());
+        assert!(Record::parse("", Rules::CLASSIC).is_err());
+    }
+
+    #[test]
+    fn test_times() {
+        let text = "..../..../..../.... - 1 clock=1+0/60/60\n9@58.5 0,0:0@55 1,1:f@0";
+        let record = Record::parse(text, Rules::CLASSIC).unwrap();
+        assert_eq!(record.times.len(), 3);
+        assert_eq!(record.to_string(), text);
+        let positions = record.positions();
+        let clock = positions[2].clock.unwrap();
+        assert_eq!(
+            clock.remaining(Player::PlayerOne),
+            Duration::from_millis(58_500)
+        );
+        assert_eq!(clock.remaining(Player::PlayerTwo), Duration::from_secs(55));
+        // Player one ran out of time on their second move.
+        assert_eq!(positions[3].winner(), Some(Player::PlayerTwo));
+
+        let missing = "..../..../..../.... - 1 clock=1+0/60/60\n9@58.5 0,0:0";
+        assert_eq!(
+            Record::parse(missing, Rules::CLASSIC).unwrap_err(),
+            "Missing time of move 2"
+        );
+        let unclocked = "..../..../..../.... - 1\n9@58.5";
+        assert!(Record::parse(unclocked, Rules::CLASSIC).is_err());
+    }
+
+    #[test]
+    fn test_find_move() {
+        let mut game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        let mut ai = RandomAi::with_seed(7);
+        let mut record = Record::new(game.clone());
+        while game.running() {
+            let next = ai.play(&game);
+            record.push(
+                Record::find_move(&game, &next).unwrap(),
+                game.player(),
+                &next,
+            );
+            game = next;
+        }
+        let parsed = Record::parse(&record.to_string(), Rules::CLASSIC).unwrap();
+        let last = parsed.positions().pop().unwrap();
+        assert_eq!(last.field, game.field);
+        assert_eq!(last.status, game.status);
+    }
+}
@@ -11,6 +11,17 @@ pub fn time_nanos() -> u128 {
         .as_nanos()
 }
 
+/// Derives the seed of the `index`th sub-task, e.g. a simulated game, from a master `seed`.
+/// The result only depends on the two inputs, not on the order in which the sub-tasks run.
+#[must_use]
+pub fn split_seed(seed: u64, index: u64) -> u64 {
+    // splitmix64, see <https://prng.di.unimi.it/splitmix64.c>
+    let mut z = seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 /// Taken from <https://github.com/AFLplusplus/LibAFL/blob/main/libafl_bolts/src/rands.rs>
 #[derive(Copy, Clone, Debug)]
 pub struct RomuDuoJrRand {
@@ -66,6 +77,15 @@ impl RomuDuoJrRand {
         unbiased_rnd % upper_bound_excl
     }
 
+    /// Shuffles the given slice in place, using the Fisher-Yates shuffle.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            #[allow(clippy::cast_possible_truncation)]
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+
     /// Choose an item at random from the given iterator, sampling uniformly.
     ///
     /// Note: the runtime cost is bound by the iterator's [`nth`][`Iterator::nth`] implementation
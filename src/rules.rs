@@ -0,0 +1,319 @@
+//! The rules of a game: the size of the field, the lines that win, and how a game without a
+//! winning line ends.
+//!
+//! Rules are written as `key=value` pairs, separated by `,`, e.g.
+//! `size=4,lines=rows+diagonals+squares,draw=draw,variants=call-quarto`.
+//! In the misère variant, completing a line loses instead.
+//! Missing keys keep the classic rules.
+
+use core::{fmt::Display, str::FromStr};
+
+use crate::{field::Field, game::Player};
+
+/// The kinds of lines that win, if all pieces on them share an attribute.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct WinLines {
+    /// All rows and columns.
+    pub rows: bool,
+    /// Both diagonals.
+    pub diagonals: bool,
+    /// The diagonals that wrap around the edges of the field, as if it was a torus.
+    pub broken_diagonals: bool,
+    /// All squares of 2x2 pieces.
+    pub squares: bool,
+    /// The squares of 2x2 pieces that wrap around the edges of the field.
+    pub wrapping_squares: bool,
+}
+
+impl WinLines {
+    /// The lines of the classic game: rows, columns and diagonals.
+    pub const CLASSIC: Self = Self {
+        rows: true,
+        diagonals: true,
+        broken_diagonals: false,
+        squares: false,
+        wrapping_squares: false,
+    };
+
+    /// The names of the kinds of lines, in the order they are written.
+    const NAMES: [&'static str; 5] = [
+        "rows",
+        "diagonals",
+        "broken-diagonals",
+        "squares",
+        "wrapping-squares",
+    ];
+
+    fn flags(&mut self) -> [&mut bool; 5] {
+        [
+            &mut self.rows,
+            &mut self.diagonals,
+            &mut self.broken_diagonals,
+            &mut self.squares,
+            &mut self.wrapping_squares,
+        ]
+    }
+}
+
+/// What happens if the field is full, or all pieces are used, without a winning line.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DrawRule {
+    /// Nobody wins.
+    #[default]
+    Draw,
+    /// The player that placed the last piece wins.
+    LastPlayerWins,
+    /// The player that placed the last piece loses.
+    LastPlayerLoses,
+}
+
+impl DrawRule {
+    /// The winner of a game that ended without a winning line, after `last_player` moved.
+    pub fn winner(self, last_player: Player) -> Option<Player> {
+        match self {
+            Self::Draw => None,
+            Self::LastPlayerWins => Some(last_player),
+            Self::LastPlayerLoses => Some(last_player.next()),
+        }
+    }
+}
+
+/// The rules of a game, see [`Rules::CLASSIC`] for the classic game.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Rules {
+    /// The width and height of the field, and the number of attributes of the pieces.
+    pub size: usize,
+    pub lines: WinLines,
+    pub draw: DrawRule,
+    /// A player has to call "Quarto!" when completing a line, or the line does not win.
+    /// The opponent can then claim the missed win, before placing their piece.
+    pub call_quarto: bool,
+    /// Completing a line loses the game, instead of winning it.
+    pub misere: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::CLASSIC
+    }
+}
+
+impl Rules {
+    /// The classic game on a 4x4 field, where rows, columns and diagonals win.
+    pub const CLASSIC: Self = Self {
+        size: Field::DEFAULT_SIZE,
+        lines: WinLines::CLASSIC,
+        draw: DrawRule::Draw,
+        call_quarto: false,
+        misere: false,
+    };
+
+    /// The names of the variants, in the order they are written.
+    const VARIANTS: [&'static str; 2] = ["call-quarto", "misere"];
+
+    fn variants(&mut self) -> [&mut bool; 2] {
+        [&mut self.call_quarto, &mut self.misere]
+    }
+
+    /// The winner of a game in which `player` completed a line.
+    pub fn line_winner(&self, player: Player) -> Player {
+        if self.misere {
+            player.next()
+        } else {
+            player
+        }
+    }
+
+    /// Lets the lines wrap around the edges of the field, as if it was a torus: the broken
+    /// diagonals win, and if squares win, so do the squares across the edges.
+    pub fn make_toroidal(&mut self) {
+        self.lines.broken_diagonals = true;
+        self.lines.wrapping_squares |= self.lines.squares;
+    }
+
+    /// Checks that a game can be played with these rules.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(Field::MIN_SIZE..=Field::MAX_SIZE).contains(&self.size) {
+            return Err(format!(
+                "The size has to be between {} and {}",
+                Field::MIN_SIZE,
+                Field::MAX_SIZE
+            ));
+        }
+        let mut lines = self.lines;
+        if !lines.flags().iter().any(|&&mut flag| flag) {
+            return Err("At least one kind of line has to win".to_string());
+        }
+        if self.call_quarto && self.misere {
+            return Err(
+                "Nobody calls \"Quarto!\" to lose, misere excludes call-quarto".to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Rules {
+    type Err = String;
+
+    /// Parses and validates rules, see the [module documentation](self).
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut rules = Self::default();
+        for param in s
+            .split(',')
+            .map(str::trim)
+            .filter(|param| !param.is_empty())
+        {
+            let Some((key, value)) = param.split_once('=') else {
+                return Err(format!("Expected key=value, got: {param}"));
+            };
+            match key {
+                "size" => {
+                    rules.size = value
+                        .parse()
+                        .map_err(|_| format!("Invalid size: {value}"))?;
+                }
+                "lines" => {
+                    let mut lines = WinLines {
+                        rows: false,
+                        diagonals: false,
+                        broken_diagonals: false,
+                        squares: false,
+                        wrapping_squares: false,
+                    };
+                    for name in value.split('+') {
+                        let i = WinLines::NAMES
+                            .iter()
+                            .position(|&known| known == name)
+                            .ok_or_else(|| {
+                                format!(
+                                    "Invalid lines: {name}, available: {}",
+                                    WinLines::NAMES.join(", ")
+                                )
+                            })?;
+                        *lines.flags()[i] = true;
+                    }
+                    rules.lines = lines;
+                }
+                "draw" => {
+                    rules.draw = match value {
+                        "draw" => DrawRule::Draw,
+                        "last-player-wins" => DrawRule::LastPlayerWins,
+                        "last-player-loses" => DrawRule::LastPlayerLoses,
+                        _ => {
+                            return Err(format!(
+                            "Invalid draw rule: {value}, available: draw, last-player-wins, last-player-loses"
+                        ))
+                        }
+                    };
+                }
+                "variants" => {
+                    for variant in rules.variants() {
+                        *variant = false;
+                    }
+                    for name in value.split('+').filter(|name| !name.is_empty()) {
+                        let i = Self::VARIANTS
+                            .iter()
+                            .position(|&known| known == name)
+                            .ok_or_else(|| {
+                                format!(
+                                    "Invalid variant: {name}, available: {}",
+                                    Self::VARIANTS.join(", ")
+                                )
+                            })?;
+                        *rules.variants()[i] = true;
+                    }
+                }
+                _ => return Err(format!("Unknown rule: {key}")),
+            }
+        }
+        rules.validate()?;
+        Ok(rules)
+    }
+}
+
+impl Display for Rules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines = self.lines;
+        let names: Vec<&str> = WinLines::NAMES
+            .iter()
+            .zip(lines.flags())
+            .filter(|(_, flag)| **flag)
+            .map(|(&name, _)| name)
+            .collect();
+        let draw = match self.draw {
+            DrawRule::Draw => "draw",
+            DrawRule::LastPlayerWins => "last-player-wins",
+            DrawRule::LastPlayerLoses => "last-player-loses",
+        };
+        write!(
+            f,
+            "size={},lines={},draw={draw}",
+            self.size,
+            names.join("+")
+        )?;
+
+        let mut rules = *self;
+        let variants: Vec<&str> = Self::VARIANTS
+            .iter()
+            .zip(rules.variants())
+            .filter(|(_, flag)| **flag)
+            .map(|(&name, _)| name)
+            .collect();
+        if !variants.is_empty() {
+            write!(f, ",variants={}", variants.join("+"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rules::{DrawRule, Rules};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("".parse(), Ok(Rules::default()));
+        assert_eq!(
+            Rules::default().to_string(),
+            "size=4,lines=rows+diagonals,draw=draw"
+        );
+
+        let rules: Rules = "size=5,lines=rows+wrapping-squares,draw=last-player-loses"
+            .parse()
+            .unwrap();
+        assert_eq!(rules.size, 5);
+        assert!(rules.lines.rows && rules.lines.wrapping_squares);
+        assert!(!rules.lines.diagonals && !rules.lines.squares);
+        assert_eq!(rules.draw, DrawRule::LastPlayerLoses);
+        assert_eq!(rules.to_string().parse(), Ok(rules));
+
+        let mut rules: Rules = "lines=rows+squares".parse().unwrap();
+        rules.make_toroidal();
+        assert_eq!(
+            rules.to_string(),
+            "size=4,lines=rows+broken-diagonals+squares+wrapping-squares,draw=draw"
+        );
+        assert_eq!(rules.to_string().parse(), Ok(rules));
+
+        let rules: Rules = "variants=call-quarto".parse().unwrap();
+        assert!(rules.call_quarto);
+        assert_eq!(
+            rules.to_string(),
+            "size=4,lines=rows+diagonals,draw=draw,variants=call-quarto"
+        );
+        assert_eq!("variants=".parse(), Ok(Rules::CLASSIC));
+        let rules: Rules = "variants=misere".parse().unwrap();
+        assert!(rules.misere && !rules.call_quarto);
+        assert_eq!(rules.to_string().parse(), Ok(rules));
+        assert!("variants=call-quarto+misere".parse::<Rules>().is_err());
+
+        assert!("size=7".parse::<Rules>().is_err());
+        assert!("variants=chess".parse::<Rules>().is_err());
+        assert!("lines=".parse::<Rules>().is_err());
+        assert!("lines=circles".parse::<Rules>().is_err());
+        assert!("draw=maybe".parse::<Rules>().is_err());
+        assert!("colour=red".parse::<Rules>().is_err());
+    }
+}
@@ -0,0 +1,652 @@
+//! A depth-limited [negamax](https://en.wikipedia.org/wiki/Negamax) search with alpha-beta pruning,
+//! backed by a [`TranspositionTable`].
+//!
+//! The search goes one move deeper at a time, up to its depth. It stops early when the time
+//! budget of the move runs out, or a stop signal arrives, and then plays the best move of the
+//! deepest search that finished.
+//!
+//! With more than one thread, the search runs [Lazy SMP](https://www.chessprogramming.org/Lazy_SMP):
+//! helper threads search the same position at the same time, each in its own move order, and
+//! share their results through the transposition table. Only the result of the main thread is
+//! played, which gets faster, but no longer only depends on the seed. With a single thread, the
+//! search is deterministic.
+//!
+//! While the opponent thinks, the search can [ponder](SearchAi::ponder): it searches the
+//! positions after their replies in the background, and plays the result right away if the
+//! position comes up.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    eval::{Evaluator, WeightedEvaluator},
+    game::{Game, Move, Status},
+    rng::RomuDuoJrRand,
+    strategy::Strategy,
+    tt::{Bound, Entry, TranspositionTable},
+};
+
+/// The score of a win on the next move. Wins further in the future score one less per ply.
+/// Scores are always seen from the player on the move.
+pub const WIN: i16 = 1000;
+
+/// Scores beyond this are wins or losses, and not evaluations.
+pub const WIN_THRESHOLD: i16 = WIN - 100;
+
+/// How many positions the search visits between checks of the time and the stop signal.
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Plays the best move that a negamax search to a fixed depth finds.
+#[derive(Debug)]
+pub struct SearchAi {
+    /// The number of moves to look ahead.
+    depth: u8,
+    rng: RomuDuoJrRand,
+    tt: Arc<TranspositionTable>,
+    /// Scores the positions at the end of the search.
+    evaluator: Arc<dyn Evaluator>,
+    /// The number of positions visited in the last search.
+    nodes: u64,
+    /// The depth of the last search, less than `depth` if the clock ran short.
+    depth_reached: u8,
+    /// The longest time to think about a move, besides the clock.
+    move_time: Option<Duration>,
+    /// When to give up the current search.
+    deadline: Option<Instant>,
+    /// Searches give up while this is set.
+    stop: Option<Arc<AtomicBool>>,
+    /// Whether the current depth was given up, and its result is meaningless.
+    aborted: bool,
+    /// The number of threads that search each position, see the
+    /// [module documentation](self).
+    threads: usize,
+    /// The best move and score of each position searched while pondering, by its hash.
+    pondered: Arc<Mutex<HashMap<u64, (Move, i16)>>>,
+    /// Print the result of each search.
+    reasoning: bool,
+}
+
+impl SearchAi {
+    /// Creates a new search AI, using (and possibly sharing) the given transposition table.
+    /// Positions at the end of the search are scored by a [`WeightedEvaluator`] with the
+    /// installed weights.
+    pub fn new(depth: u8, seed: u64, tt: Arc<TranspositionTable>) -> Self {
+        Self {
+            depth: depth.max(1),
+            rng: RomuDuoJrRand::with_seed(seed),
+            tt,
+            evaluator: Arc::new(WeightedEvaluator::default()),
+            nodes: 0,
+            depth_reached: 0,
+            move_time: None,
+            deadline: None,
+            stop: None,
+            aborted: false,
+            threads: 1,
+            pondered: Arc::default(),
+            reasoning: false,
+        }
+    }
+
+    /// Searches each position on `threads` threads, see the [module documentation](self).
+    #[must_use]
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Thinks at most `move_time` about each move, even if the clock would allow more.
+    #[must_use]
+    pub fn with_move_time(mut self, move_time: Option<Duration>) -> Self {
+        self.move_time = move_time;
+        self
+    }
+
+    /// Gives up searching deeper while `stop` is set, and plays the best move found so far.
+    /// The signal is never reset by the search.
+    #[must_use]
+    pub fn with_stop(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Scores the positions at the end of the search with `evaluator`.
+    /// The transposition table must not be shared with searches that use other evaluators.
+    #[must_use]
+    pub fn with_evaluator(mut self, evaluator: Arc<dyn Evaluator>) -> Self {
+        self.evaluator = evaluator;
+        self
+    }
+
+    /// Prints the result of each search during the game.
+    #[must_use]
+    pub fn with_reasoning(mut self, reasoning: bool) -> Self {
+        self.reasoning = reasoning;
+        self
+    }
+
+    /// Searches the current position, and returns the best move with its score.
+    /// Moves with the same score are picked at random.
+    /// The search goes one move deeper at a time, up to the depth, as long as the next depth
+    /// likely fits into the time budget, from the clock or the move time, see the
+    /// [module documentation](self).
+    /// Returns `None` if the game is over.
+    pub fn best_move(&mut self, game: &Game) -> Option<(Move, i16)> {
+        let pondered = self.pondered.lock().unwrap().remove(&game.zobrist_hash());
+        if let Some(result) = pondered {
+            self.nodes = 0;
+            self.depth_reached = self.depth;
+            return Some(result);
+        }
+        self.tt.new_search();
+        if self.threads == 1 {
+            return self.search(game);
+        }
+
+        // The helpers run until the main thread is done.
+        let done = Arc::new(AtomicBool::new(false));
+        let mut helpers: Vec<Self> = (1..self.threads)
+            .map(|_| {
+                Self::new(self.depth, self.rng.next(), self.tt.clone())
+                    .with_evaluator(self.evaluator.clone())
+                    .with_move_time(self.move_time)
+                    .with_stop(done.clone())
+            })
+            .collect();
+        let best = thread::scope(|s| {
+            for helper in &mut helpers {
+                s.spawn(|| helper.search(game));
+            }
+            let best = self.search(game);
+            done.store(true, Ordering::Relaxed);
+            best
+        });
+        self.nodes += helpers.iter().map(|helper| helper.nodes).sum::<u64>();
+        best
+    }
+
+    /// Searches the positions after the replies of the opponent, on the move in `game`, on a
+    /// background thread, until the returned [`Ponder`] is dropped. The most likely reply comes
+    /// first, then all others. If the next move is searched in one of these positions, the result
+    /// is played right away. Returns `None` if there is nothing to search.
+    pub fn ponder(&mut self, game: &Game) -> Option<Ponder> {
+        if !game.running() || game.is_initial_move() {
+            return None;
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut search = Self::new(self.depth, self.rng.next(), self.tt.clone())
+            .with_evaluator(self.evaluator.clone())
+            .with_threads(self.threads)
+            .with_stop(stop.clone());
+        // The opponent's time is not ours to manage.
+        let mut game = game.clone();
+        game.clock = None;
+        let stopped = stop.clone();
+        let pondered = self.pondered.clone();
+        pondered.lock().unwrap().clear();
+        let handle = thread::spawn(move || {
+            let mut replies = game.legal_moves();
+            if let Some((likely, _)) = search.best_move(&game) {
+                let i = replies.iter().position(|&mv| mv == likely).unwrap();
+                replies[..=i].rotate_right(1);
+            }
+            for reply in replies {
+                if stopped.load(Ordering::Relaxed) {
+                    return;
+                }
+                let mut after = game.clone();
+                after.play_move(reply).unwrap();
+                if !after.running() {
+                    continue;
+                }
+                // Only finished searches are as good as the one they replace.
+                if let Some(result) = search.best_move(&after).filter(|(_, score)| {
+                    search.depth_reached == search.depth || score.abs() > WIN_THRESHOLD
+                }) {
+                    pondered
+                        .lock()
+                        .unwrap()
+                        .insert(after.zobrist_hash(), result);
+                }
+            }
+        });
+        Some(Ponder {
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Searches the current position on this thread, see [`SearchAi::best_move`].
+    fn search(&mut self, game: &Game) -> Option<(Move, i16)> {
+        self.nodes = 0;
+
+        let mut moves = game.legal_moves();
+        if moves.is_empty() {
+            return None;
+        }
+        self.rng.shuffle(&mut moves);
+
+        if game.is_initial_move() {
+            // All pieces are equivalent on the empty field, see `crate::symmetry`.
+            return Some((moves[0], 0));
+        }
+
+        let start = Instant::now();
+        let budget = game
+            .clock
+            .map(|clock| clock.budget(game.player(), game.field.empty_spaces().len().div_ceil(2)))
+            .into_iter()
+            .chain(self.move_time)
+            .min();
+        self.deadline = budget.and_then(|budget| start.checked_add(budget));
+        self.aborted = false;
+        self.depth_reached = 0;
+
+        let mut best = None;
+        for depth in 1..=self.depth {
+            if depth > 1 && self.should_stop() {
+                break;
+            }
+            let result = self.search_root(game, &moves, depth);
+            if self.aborted {
+                break;
+            }
+            best = Some(result);
+            self.depth_reached = depth;
+            if self.reasoning {
+                self.pp_progress(result.1, start.elapsed());
+            }
+            if result.1.abs() > WIN_THRESHOLD {
+                break;
+            }
+            // The next depth takes several times as long as all before, so it would likely be
+            // given up anyway.
+            if budget.is_some_and(|budget| start.elapsed() > budget / 2) {
+                break;
+            }
+        }
+        self.deadline = None;
+        best
+    }
+
+    /// Prints the depth, score and speed of the search so far.
+    fn pp_progress(&self, score: i16, elapsed: Duration) {
+        #[allow(clippy::cast_precision_loss)]
+        let nps = self.nodes as f64 / elapsed.as_secs_f64().max(1e-9);
+        println!(
+            "AI: depth {}, score {score}, {} nodes, {nps:.0} nodes/s",
+            self.depth_reached, self.nodes
+        );
+    }
+
+    /// Returns true if the search has to give up, because the time ran out, or it was stopped.
+    fn should_stop(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Returns the score of playing `mv` in `game`, searching as deep as [`SearchAi::best_move`].
+    pub fn evaluate_move(&mut self, game: &Game, mv: Move) -> i16 {
+        self.tt.new_search();
+        self.score_move(game, mv, self.depth - 1, 0, -WIN - 1, WIN + 1)
+    }
+
+    /// Searches all `moves` `depth` moves deep, and returns the best one with its score.
+    fn search_root(&mut self, game: &Game, moves: &[Move], depth: u8) -> (Move, i16) {
+        let mut best = (moves[0], -WIN - 1);
+        for &mv in moves {
+            let score = self.score_move(game, mv, depth - 1, 0, best.1, WIN + 1);
+            if self.aborted {
+                break;
+            }
+            if score > best.1 {
+                best = (mv, score);
+            }
+        }
+        best
+    }
+
+    /// Returns the score of playing `mv` in `game` at `ply`, from the view of the player on the
+    /// move, searching `depth` moves after it.
+    fn score_move(
+        &mut self,
+        game: &Game,
+        mv: Move,
+        depth: u8,
+        ply: u8,
+        alpha: i16,
+        beta: i16,
+    ) -> i16 {
+        let mut child = game.clone();
+        child.play_move(mv).unwrap();
+        // Depending on the rules, the game can also be won by the player that did not move.
+        match child.status {
+            Status::Won { winner } if winner == game.player() => WIN - i16::from(ply) - 1,
+            Status::Won { .. } => -(WIN - i16::from(ply) - 1),
+            Status::Draw { .. } => 0,
+            _ => -self.negamax(&child, depth, ply + 1, -beta, -alpha),
+        }
+    }
+
+    /// Returns the score of `game`, from the view of the player on the move, or a bound for it,
+    /// if it's outside of `alpha..beta`. The game must not be over.
+    fn negamax(&mut self, game: &Game, depth: u8, ply: u8, mut alpha: i16, beta: i16) -> i16 {
+        self.nodes += 1;
+        // The first depth always finishes, so that there is a move to play.
+        if self.depth_reached > 0 && self.nodes.is_multiple_of(CHECK_INTERVAL) && self.should_stop()
+        {
+            self.aborted = true;
+        }
+        if self.aborted {
+            return 0;
+        }
+
+        let Status::Move {
+            next_piece: piece, ..
+        } = game.status
+        else {
+            unreachable!("We never search the initial move, or finished games");
+        };
+
+        // A win right away is as good as it gets, no need to look any further.
+        // With the "call Quarto" rule, we always call, and claim the wins our opponent missed.
+        // In misère, completing a line loses, so there is no such shortcut, and
+        // `Self::score_move` sees the loss.
+        if game.is_claimable() {
+            return WIN - i16::from(ply) - 1;
+        }
+        if !game.rules().misere {
+            for pos in game.field.empty_spaces() {
+                let mut field = game.field.clone();
+                field.put(pos, piece).unwrap();
+                if field.wins_through(pos) {
+                    return WIN - i16::from(ply) - 1;
+                }
+            }
+        }
+
+        if depth == 0 {
+            return self.evaluator.evaluate(game);
+        }
+
+        let hash = game.zobrist_hash();
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.probe(hash) {
+            let score = from_tt(entry.score, ply);
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+            if let (Some(pos), Some(next_piece)) = (entry.pos, entry.piece) {
+                tt_move = Some(Move::Place { pos, next_piece });
+            }
+        }
+
+        let mut moves = game.legal_moves();
+        if let Some(tt_move) = tt_move {
+            if let Some(i) = moves.iter().position(|&mv| mv == tt_move) {
+                moves.swap(0, i);
+            }
+        }
+
+        let original_alpha = alpha;
+        let mut best = (moves[0], -WIN - 1);
+        for mv in moves {
+            let score = self.score_move(game, mv, depth - 1, ply, alpha, beta);
+            if self.aborted {
+                // Don't store the meaningless result.
+                return 0;
+            }
+            if score > best.1 {
+                best = (mv, score);
+                if score > alpha {
+                    alpha = score;
+                    if alpha >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let bound = if best.1 <= original_alpha {
+            Bound::Upper
+        } else if best.1 >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        // Wins by calling "Quarto!" were found above, so the best move is always a placement.
+        let Move::Place { pos, next_piece } = best.0 else {
+            unreachable!("Only placements are searched");
+        };
+        self.tt.store(
+            hash,
+            Entry {
+                score: to_tt(best.1, ply),
+                depth,
+                bound,
+                pos: Some(pos),
+                piece: Some(next_piece),
+            },
+        );
+
+        best.1
+    }
+}
+
+/// Wins and losses are stored relative to the position, not to the root of the search.
+fn to_tt(score: i16, ply: u8) -> i16 {
+    if score > WIN_THRESHOLD {
+        score + i16::from(ply)
+    } else if score < -WIN_THRESHOLD {
+        score - i16::from(ply)
+    } else {
+        score
+    }
+}
+
+fn from_tt(score: i16, ply: u8) -> i16 {
+    if score > WIN_THRESHOLD {
+        score - i16::from(ply)
+    } else if score < -WIN_THRESHOLD {
+        score + i16::from(ply)
+    } else {
+        score
+    }
+}
+
+/// A search in the background, see [`SearchAi::ponder`]. Dropping it stops the search, and waits
+/// for it to finish.
+#[derive(Debug)]
+pub struct Ponder {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Ponder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Strategy for SearchAi {
+    fn play(&mut self, game: &Game) -> Game {
+        let it = Instant::now();
+        let (mv, score) = self
+            .best_move(game)
+            .expect("Game should just terminate here.");
+        if self.reasoning {
+            println!(
+                "AI: Searched {} positions, {} moves deep, in {:.4} us. Score: {score}",
+                self.nodes,
+                self.depth_reached,
+                it.elapsed().as_micros()
+            );
+        }
+        let mut game = game.clone();
+        game.play_move(mv).expect("AI should only do legal moves");
+        game
+    }
+
+    fn ponder(&mut self, game: &Game) -> Option<Ponder> {
+        SearchAi::ponder(self, game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicBool, Arc},
+        thread,
+        time::Duration,
+    };
+
+    use crate::{
+        ai::RandomAi,
+        game::{Game, Move, Player},
+        notation::parse_notation,
+        rules::Rules,
+        search::{SearchAi, WIN},
+        strategy::Strategy,
+        tt::TranspositionTable,
+    };
+
+    #[test]
+    fn test_finds_win() {
+        // 0, 1, 2 and 3 all lack properties 4 and 8, so 3 completes the top row.
+        let game = parse_notation("012./..../..../.... 3 1").unwrap();
+        let mut ai = SearchAi::new(2, 1, Arc::new(TranspositionTable::with_size_mb(1)));
+        let (mv, score) = ai.best_move(&game).unwrap();
+        assert_eq!(score, WIN - 1);
+        assert!(matches!(mv, Move::Place { pos: (3, 0), .. }));
+    }
+
+    #[test]
+    fn test_finds_broken_diagonal() {
+        let game = parse_notation(
+            ".0../..1./...2/.... 3 1 size=4,lines=rows+diagonals+broken-diagonals,draw=draw",
+        )
+        .unwrap();
+        let mut ai = SearchAi::new(2, 1, Arc::new(TranspositionTable::with_size_mb(1)));
+        let (mv, score) = ai.best_move(&game).unwrap();
+        assert_eq!(score, WIN - 1);
+        assert!(matches!(mv, Move::Place { pos: (0, 3), .. }));
+    }
+
+    #[test]
+    fn test_avoids_giving_away_win() {
+        // Pieces with property 8, or without property 1, complete the top row.
+        // The AI has to either block the row with 3, or give away 1, 5 or 7.
+        let game = parse_notation("e8c./..../..../.... 3 2").unwrap();
+        let mut ai = SearchAi::new(2, 1, Arc::new(TranspositionTable::with_size_mb(1)));
+        let (mv, score) = ai.best_move(&game).unwrap();
+        assert!(score > -WIN + 100, "{mv:?} loses");
+        let mut game = game.clone();
+        game.play_move(mv).unwrap();
+        assert_eq!(game.player(), Player::PlayerOne);
+        // The opponent can't win right away.
+        assert_ne!(ai.best_move(&game).unwrap().1, WIN - 1);
+    }
+
+    #[test]
+    fn test_misere_avoids_line() {
+        // As in `test_finds_win`, but completing the top row loses.
+        let game = parse_notation("012./..../..../.... 3 1 variants=misere").unwrap();
+        let mut ai = SearchAi::new(2, 1, Arc::new(TranspositionTable::with_size_mb(1)));
+        let (mv, score) = ai.best_move(&game).unwrap();
+        assert!(score > -WIN + 100, "{mv:?} loses");
+        assert!(!matches!(mv, Move::Place { pos: (3, 0), .. }));
+    }
+
+    #[test]
+    fn test_respects_clock() {
+        // With 60 ms left, there is no time to look 4 moves ahead.
+        let game = parse_notation("9.../.0../..../.... f 1 clock=1+0/0.06/60").unwrap();
+        let mut ai = SearchAi::new(4, 1, Arc::new(TranspositionTable::with_size_mb(1)));
+        let (mv, _) = ai.best_move(&game).unwrap();
+        assert!(ai.depth_reached < 4);
+        assert!(game.legal_moves().contains(&mv));
+    }
+
+    #[test]
+    fn test_threads() {
+        let mut game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        let mut random = RandomAi::with_seed(1);
+        while game.running() && game.field.empty_spaces().len() > 7 {
+            game = random.play(&game);
+        }
+        assert!(game.running());
+        let tt = || Arc::new(TranspositionTable::with_size_mb(1));
+
+        // A single thread only depends on the seed.
+        let best = SearchAi::new(8, 1, tt()).best_move(&game);
+        assert_eq!(SearchAi::new(8, 1, tt()).best_move(&game), best);
+
+        // Searching to the end, all threads agree on the score.
+        let (mv, score) = SearchAi::new(8, 1, tt())
+            .with_threads(4)
+            .best_move(&game)
+            .unwrap();
+        assert_eq!(score, best.unwrap().1);
+        assert!(game.legal_moves().contains(&mv));
+    }
+
+    #[test]
+    fn test_ponder() {
+        let mut game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        let mut random = RandomAi::with_seed(1);
+        while game.running() && game.field.empty_spaces().len() > 7 {
+            game = random.play(&game);
+        }
+        let tt = || Arc::new(TranspositionTable::with_size_mb(1));
+        let mut ai = SearchAi::new(3, 1, tt());
+        let ponder = ai.ponder(&game).unwrap();
+        // Plenty of time to search after each reply.
+        thread::sleep(Duration::from_secs(1));
+        drop(ponder);
+
+        // Whatever the reply, the position after it was searched while pondering.
+        let game = random.play(&game);
+        assert!(game.running());
+        let (mv, score) = ai.best_move(&game).unwrap();
+        assert_eq!(ai.nodes, 0);
+        assert!(game.legal_moves().contains(&mv));
+        let (_, fresh) = SearchAi::new(3, 1, tt()).best_move(&game).unwrap();
+        assert_eq!(score, fresh);
+    }
+
+    #[test]
+    fn test_stops() {
+        let game = parse_notation("9.../.0../..../.... f 1").unwrap();
+        let tt = || Arc::new(TranspositionTable::with_size_mb(1));
+        let mut ai = SearchAi::new(6, 1, tt()).with_move_time(Some(Duration::ZERO));
+        let (mv, _) = ai.best_move(&game).unwrap();
+        assert_eq!(ai.depth_reached, 1);
+        assert!(game.legal_moves().contains(&mv));
+
+        // A stopped search still plays a move, from the first depth.
+        let stop = Arc::new(AtomicBool::new(true));
+        let mut ai = SearchAi::new(6, 1, tt()).with_stop(stop);
+        let (mv, _) = ai.best_move(&game).unwrap();
+        assert_eq!(ai.depth_reached, 1);
+        assert!(game.legal_moves().contains(&mv));
+    }
+}
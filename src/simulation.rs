@@ -0,0 +1,431 @@
+//! Simulated AI vs AI battles, spread over multiple threads.
+
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::TimeControl,
+    game::{Game, Player},
+    notation::to_notation,
+    rng::{split_seed, RomuDuoJrRand},
+    rules::Rules,
+    strategy::{Strategy, StrategySpec},
+    symmetry::CanonicalKey,
+};
+
+/// The outcome of a single simulated game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameResult {
+    /// The seed this game was played with.
+    pub seed: u64,
+    /// The winner, or `None` for a draw.
+    pub winner: Option<Player>,
+    /// The round in which the game ended.
+    pub rounds: u8,
+    /// The number of moves played from the starting position, claims included.
+    pub plies: usize,
+    /// The final position, up to symmetry.
+    pub final_position: CanonicalKey,
+    /// The final position, see [`crate::notation`].
+    pub final_notation: String,
+}
+
+/// Plays a single game, starting from `base_game`. Player one always starts.
+/// With `reasoning`, each position is printed, and the strategies explain their moves.
+pub fn play_game(
+    base_game: &Game,
+    strategies: &[StrategySpec; 2],
+    seed: u64,
+    reasoning: bool,
+) -> GameResult {
+    let mut rng = RomuDuoJrRand::with_seed(seed);
+    let ais = [
+        strategies[0].build(Player::PlayerOne, rng.next(), reasoning),
+        strategies[1].build(Player::PlayerTwo, rng.next(), reasoning),
+    ];
+    play_game_with(base_game, ais, seed, reasoning)
+}
+
+/// Plays a single game between `ais`, for player one and two, starting from `base_game`.
+/// The `seed` the strategies were created with is only recorded in the result.
+pub fn play_game_with(
+    base_game: &Game,
+    mut ais: [Box<dyn Strategy>; 2],
+    seed: u64,
+    reasoning: bool,
+) -> GameResult {
+    let mut game = base_game.clone();
+    let mut plies = 0;
+    loop {
+        if reasoning {
+            game.pp();
+        }
+        if !game.running() {
+            return GameResult {
+                seed,
+                winner: game.winner(),
+                rounds: game.round(),
+                plies,
+                final_position: game.canonical_key(),
+                final_notation: to_notation(&game),
+            };
+        }
+
+        let player = game.player();
+        let start = Instant::now();
+        if player == Player::PlayerOne {
+            game = ais[0].play(&game);
+        } else {
+            game = ais[1].play(&game);
+        }
+        game.charge_clock(player, start.elapsed());
+        plies += 1;
+    }
+}
+
+/// Plays the games of `config`, starting from `base_game`.
+/// Each game gets its own seed, derived from the seed of `config`, so the results (returned in
+/// order) do not depend on the number of threads.
+pub fn run(base_game: &Game, config: &Config, reasoning: bool) -> Vec<GameResult> {
+    run_with(config.seed, config.games, config.threads, |_, game_seed| {
+        play_game(base_game, &config.strategies, game_seed, reasoning)
+    })
+}
+
+/// Plays `games` games on `threads` threads, by calling `play` with the index and seed of each
+/// game, see [`run`].
+pub fn run_with(
+    seed: u64,
+    games: usize,
+    threads: usize,
+    play: impl Fn(usize, u64) -> GameResult + Sync,
+) -> Vec<GameResult> {
+    let next_game = AtomicUsize::new(0);
+
+    let mut results: Vec<(usize, GameResult)> = thread::scope(|s| {
+        let workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                s.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let i = next_game.fetch_add(1, Ordering::Relaxed);
+                        if i >= games {
+                            return results;
+                        }
+                        results.push((i, play(i, split_seed(seed, i as u64))));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// How often something happened, with a 95% confidence interval for its rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frequency {
+    pub count: usize,
+    pub rate: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Frequency {
+    /// Counts `count` of `total` events, with a [Wilson score interval](https://en.wikipedia.org/wiki/Binomial_proportion_confidence_interval#Wilson_score_interval).
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(count: usize, total: usize) -> Self {
+        // The 97.5% quantile of the normal distribution, for a two-sided 95% interval.
+        const Z: f64 = 1.959_963_984_540_054;
+
+        if total == 0 {
+            return Self {
+                count,
+                rate: 0.,
+                low: 0.,
+                high: 1.,
+            };
+        }
+        let n = total as f64;
+        let rate = count as f64 / n;
+        let center = (rate + Z * Z / (2. * n)) / (1. + Z * Z / n);
+        let margin = Z / (1. + Z * Z / n) * (rate * (1. - rate) / n + Z * Z / (4. * n * n)).sqrt();
+        Self {
+            count,
+            rate,
+            low: (center - margin).max(0.),
+            high: (center + margin).min(1.),
+        }
+    }
+}
+
+/// Wins, draws and losses over a series of games, seen from player one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub games: usize,
+    pub wins: Frequency,
+    pub draws: Frequency,
+    pub losses: Frequency,
+}
+
+impl Summary {
+    pub fn new(results: &[GameResult]) -> Self {
+        let games = results.len();
+        let count = |winner| {
+            let count = results
+                .iter()
+                .filter(|result| result.winner == winner)
+                .count();
+            Frequency::new(count, games)
+        };
+        Self {
+            games,
+            wins: count(Some(Player::PlayerOne)),
+            draws: count(None),
+            losses: count(Some(Player::PlayerTwo)),
+        }
+    }
+}
+
+/// The output formats of a simulation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The setup of a simulation, printed along with its results.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub rules: Rules,
+    pub time_control: Option<TimeControl>,
+    pub strategies: [StrategySpec; 2],
+    pub seed: u64,
+    pub games: usize,
+    pub threads: usize,
+}
+
+impl Config {
+    /// Creates the setup for `games` games between `strategies` on `threads` threads, with the
+    /// rules and clock of `base_game`.
+    pub fn new(
+        base_game: &Game,
+        strategies: [StrategySpec; 2],
+        seed: u64,
+        games: usize,
+        threads: usize,
+    ) -> Self {
+        Self {
+            rules: *base_game.rules(),
+            time_control: base_game.clock.map(|clock| clock.control),
+            strategies,
+            seed,
+            games,
+            threads,
+        }
+    }
+}
+
+fn winner_str(winner: Option<Player>) -> &'static str {
+    match winner {
+        Some(Player::PlayerOne) => "1",
+        Some(Player::PlayerTwo) => "2",
+        None => "draw",
+    }
+}
+
+/// Prints the results of a simulation in the given format.
+#[allow(clippy::cast_precision_loss)]
+pub fn report(format: Format, config: &Config, results: &[GameResult], elapsed: Duration) {
+    let summary = Summary::new(results);
+    match format {
+        Format::Text => report_text(config, results, &summary, elapsed),
+        Format::Json => {
+            let count_json = |count: &Frequency| {
+                format!(
+                    "{{\"count\": {}, \"rate\": {:.4}, \"ci95\": [{:.4}, {:.4}]}}",
+                    count.count, count.rate, count.low, count.high
+                )
+            };
+            println!("{{");
+            println!("  \"rules\": \"{}\",", config.rules);
+            match config.time_control {
+                Some(control) => println!("  \"clock\": \"{control}\","),
+                None => println!("  \"clock\": null,"),
+            }
+            println!("  \"player_one\": \"{}\",", config.strategies[0]);
+            println!("  \"player_two\": \"{}\",", config.strategies[1]);
+            println!("  \"seed\": {},", config.seed);
+            println!("  \"games\": {},", config.games);
+            println!("  \"threads\": {},", config.threads);
+            println!("  \"elapsed_secs\": {:.3},", elapsed.as_secs_f64());
+            println!("  \"wins\": {},", count_json(&summary.wins));
+            println!("  \"draws\": {},", count_json(&summary.draws));
+            println!("  \"losses\": {},", count_json(&summary.losses));
+            println!("  \"results\": [");
+            for (i, result) in results.iter().enumerate() {
+                let winner = match result.winner {
+                    Some(_) => winner_str(result.winner),
+                    None => "null",
+                };
+                let separator = if i + 1 < results.len() { "," } else { "" };
+                println!(
+                    "    {{\"game\": {i}, \"seed\": {}, \"winner\": {winner}, \"plies\": {}, \"final_position\": \"{}\"}}{separator}",
+                    result.seed, result.plies, result.final_notation
+                );
+            }
+            println!("  ]");
+            println!("}}");
+        }
+        Format::Csv => {
+            println!("game,seed,winner,plies,final_position");
+            for (i, result) in results.iter().enumerate() {
+                // Notations with rules contain commas.
+                println!(
+                    "{i},{},{},{},\"{}\"",
+                    result.seed,
+                    winner_str(result.winner),
+                    result.plies,
+                    result.final_notation
+                );
+            }
+            let count_csv = |name: &str, count: &Frequency| {
+                println!(
+                    "# {name}={},rate={:.4},ci95_low={:.4},ci95_high={:.4}",
+                    count.count, count.rate, count.low, count.high
+                );
+            };
+            println!(
+                "# player_one={},player_two={},seed={},games={}",
+                config.strategies[0], config.strategies[1], config.seed, config.games
+            );
+            println!("# rules: {}", config.rules);
+            if let Some(control) = config.time_control {
+                println!("# clock: {control}");
+            }
+            count_csv("wins", &summary.wins);
+            count_csv("draws", &summary.draws);
+            count_csv("losses", &summary.losses);
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss, clippy::cast_lossless)]
+fn report_text(config: &Config, results: &[GameResult], summary: &Summary, elapsed: Duration) {
+    let games = results.len();
+    let turns: u64 = results.iter().map(|result| result.rounds as u64).sum();
+    let final_positions: std::collections::HashSet<_> = results
+        .iter()
+        .map(|result| &result.final_position)
+        .collect();
+
+    println!("Rules: {}", config.rules);
+    if let Some(control) = config.time_control {
+        println!("Clock: {control}");
+    }
+    println!(
+        "Player 1 ({}) vs. Player 2 ({})",
+        config.strategies[0], config.strategies[1]
+    );
+    if games == 0 {
+        println!("Did no games");
+        return;
+    }
+    println!(
+        "Did {} games in {:.3} seconds({:05.3} games/sec)",
+        games,
+        elapsed.as_secs_f64(),
+        games as f64 / elapsed.as_secs_f64()
+    );
+    println!(
+        "Did {} turns in total, average of {} turns per game",
+        turns,
+        turns as f64 / games as f64
+    );
+    let pp_count = |count: &Frequency| {
+        format!(
+            "{} ({:.1}%, 95% CI {:.1}%-{:.1}%)",
+            count.count,
+            count.rate * 100.,
+            count.low * 100.,
+            count.high * 100.
+        )
+    };
+    println!("Player 1 wins: {}", pp_count(&summary.wins));
+    println!("Player 2 wins: {}", pp_count(&summary.losses));
+    println!("Draws:         {}", pp_count(&summary.draws));
+    println!(
+        "The games ended in {} distinct positions (up to symmetry)",
+        final_positions.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        game::{Game, Player},
+        notation::parse_notation,
+        rules::Rules,
+        simulation::{play_game, run, Config, Frequency},
+        strategy::StrategySpec,
+    };
+
+    #[test]
+    fn test_thread_count_independent() {
+        let game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        let strategies = [StrategySpec::Simple, StrategySpec::Random];
+        let single = run(
+            &game,
+            &Config::new(&game, strategies.clone(), 1337, 20, 1),
+            false,
+        );
+        let multi = run(&game, &Config::new(&game, strategies, 1337, 20, 4), false);
+        assert_eq!(single.len(), 20);
+        assert_eq!(single, multi);
+    }
+
+    #[test]
+    fn test_plies_from_position() {
+        // 3 completes the top row, so the game ends after a single move.
+        let game = parse_notation("012./..../..../.... 3 1").unwrap();
+        let strategies = [StrategySpec::Simple, StrategySpec::Simple];
+        let result = play_game(&game, &strategies, 1, false);
+        assert_eq!(result.winner, Some(Player::PlayerOne));
+        assert_eq!(result.plies, 1);
+    }
+
+    #[test]
+    fn test_wilson_interval() {
+        let count = Frequency::new(50, 100);
+        assert!((count.rate - 0.5).abs() < f64::EPSILON);
+        assert!((count.low - 0.4038).abs() < 0.0001);
+        assert!((count.high - 0.5962).abs() < 0.0001);
+
+        let none = Frequency::new(0, 10);
+        assert!(none.low.abs() < f64::EPSILON);
+        assert!(none.high > 0.2);
+    }
+}
@@ -0,0 +1,188 @@
+//! A [sequential probability ratio test](https://en.wikipedia.org/wiki/Sequential_probability_ratio_test)
+//! between two strategies, playing games until it is clear enough which of two hypotheses holds:
+//!  * H0: strategy A is `elo0` Elo stronger than B.
+//!  * H1: strategy A is `elo1` Elo stronger than B.
+//!
+//! Uses the usual approximation of the log-likelihood ratio for win/draw/loss results,
+//! see <https://www.chessprogramming.org/Match_Statistics#SPRT>.
+
+use crate::{
+    game::{Game, Player},
+    rng::split_seed,
+    simulation::{self, Config},
+    strategy::StrategySpec,
+    tournament::Score,
+};
+
+/// The parameters of the test.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    /// The probability of accepting H1, if H0 holds.
+    pub alpha: f64,
+    /// The probability of accepting H0, if H1 holds.
+    pub beta: f64,
+}
+
+/// The state of the test.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// We need more games.
+    Continue,
+    /// A is (at least) `elo1` stronger than B.
+    AcceptH1,
+    /// A is (at most) `elo0` stronger than B.
+    AcceptH0,
+}
+
+/// The expected score for an Elo difference.
+fn expected_score(elo: f64) -> f64 {
+    1. / (1. + 10_f64.powf(-elo / 400.))
+}
+
+impl Sprt {
+    /// The log-likelihood ratio below which H0 is accepted, and above which H1 is accepted.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1. - self.alpha)).ln(),
+            ((1. - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// The log-likelihood ratio of H1 against H0, given the score of A against B.
+    ///
+    /// To get a variance for the first, one-sided results, we add half a win, draw and loss.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn llr(&self, score: Score) -> f64 {
+        let wins = score.wins as f64 + 0.5;
+        let draws = score.draws as f64 + 0.5;
+        let losses = score.losses as f64 + 0.5;
+        let games = wins + draws + losses;
+
+        let mean = (wins + draws / 2.) / games;
+        let variance =
+            (wins * (1. - mean).powi(2) + draws * (0.5 - mean).powi(2) + losses * mean.powi(2))
+                / games;
+
+        let s0 = expected_score(self.elo0);
+        let s1 = expected_score(self.elo1);
+        (s1 - s0) * (2. * mean - s0 - s1) / (2. * variance / games)
+    }
+
+    pub fn decide(&self, llr: f64) -> Decision {
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Decision::AcceptH1
+        } else if llr <= lower {
+            Decision::AcceptH0
+        } else {
+            Decision::Continue
+        }
+    }
+}
+
+/// Plays strategy A against B, alternating who starts, until the test decides, or `max_games`
+/// have been played. Prints the running log-likelihood ratio after each batch of games.
+pub fn run(
+    base_game: &Game,
+    strategies: &[StrategySpec; 2],
+    sprt: &Sprt,
+    seed: u64,
+    threads: usize,
+    max_games: usize,
+    reasoning: bool,
+) -> (Score, Decision) {
+    let [a, b] = strategies;
+    let (lower, upper) = sprt.bounds();
+    // Enough games per color to keep all threads busy.
+    let batch = threads.max(1);
+
+    let mut score = Score::default();
+    let mut round = 0;
+    loop {
+        let config = Config::new(
+            base_game,
+            [a.clone(), b.clone()],
+            split_seed(seed, 2 * round),
+            batch,
+            threads,
+        );
+        let first = simulation::run(base_game, &config, reasoning);
+        let config = Config::new(
+            base_game,
+            [b.clone(), a.clone()],
+            split_seed(seed, 2 * round + 1),
+            batch,
+            threads,
+        );
+        let second = simulation::run(base_game, &config, reasoning);
+        for result in &first {
+            score.add(result, Player::PlayerOne);
+        }
+        for result in &second {
+            score.add(result, Player::PlayerTwo);
+        }
+
+        let llr = sprt.llr(score);
+        println!(
+            "Games: {}, W/D/L: {}/{}/{}, LLR: {llr:.3} ({lower:.3}, {upper:.3})",
+            score.games(),
+            score.wins,
+            score.draws,
+            score.losses
+        );
+
+        let decision = sprt.decide(llr);
+        if decision != Decision::Continue || score.games() >= max_games {
+            return (score, decision);
+        }
+        round += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sprt::{Decision, Sprt},
+        tournament::Score,
+    };
+
+    const SPRT: Sprt = Sprt {
+        elo0: 0.,
+        elo1: 50.,
+        alpha: 0.05,
+        beta: 0.05,
+    };
+
+    #[test]
+    fn test_bounds() {
+        let (lower, upper) = SPRT.bounds();
+        assert!((lower + 2.944).abs() < 0.001);
+        assert!((upper - 2.944).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_decisions() {
+        let even = Score {
+            wins: 400,
+            draws: 200,
+            losses: 400,
+        };
+        assert_eq!(SPRT.decide(SPRT.llr(even)), Decision::AcceptH0);
+
+        let strong = Score {
+            wins: 600,
+            draws: 200,
+            losses: 200,
+        };
+        assert_eq!(SPRT.decide(SPRT.llr(strong)), Decision::AcceptH1);
+
+        let few = Score {
+            wins: 3,
+            draws: 0,
+            losses: 2,
+        };
+        assert_eq!(SPRT.decide(SPRT.llr(few)), Decision::Continue);
+    }
+}
@@ -0,0 +1,268 @@
+//! The strategies that can play a game, and how to select them from the command line.
+
+use core::{fmt::Display, str::FromStr};
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    ai::{RandomAi, SimpleAi},
+    book, endgames,
+    game::{Game, Player},
+    search::{Ponder, SearchAi},
+    tt::TranspositionTable,
+};
+
+/// Something that can make moves in a game, e.g. an AI.
+pub trait Strategy {
+    /// Makes a move for the player on turn, returning the new game state.
+    fn play(&mut self, game: &Game) -> Game;
+
+    /// Thinks about `game` in the background, while the opponent is on the move, until the
+    /// returned [`Ponder`] is dropped. Returns `None` if this strategy does not think ahead.
+    fn ponder(&mut self, _game: &Game) -> Option<Ponder> {
+        None
+    }
+}
+
+impl Strategy for SimpleAi {
+    fn play(&mut self, game: &Game) -> Game {
+        self.play_iteratively(&mut game.clone())
+    }
+}
+
+/// Plays the move of the installed opening book or endgame table in `game`, see
+/// [`book::install`] and [`endgames::install`], if the position is in one of them.
+fn play_from_tables(game: &Game) -> Option<Game> {
+    book::play(game).or_else(|| endgames::play(game))
+}
+
+/// Plays from the installed opening book and endgame table, and lets the wrapped strategy
+/// play the positions that are in neither.
+struct WithTables(Box<dyn Strategy>);
+
+impl Strategy for WithTables {
+    fn play(&mut self, game: &Game) -> Game {
+        play_from_tables(game).unwrap_or_else(|| self.0.play(game))
+    }
+
+    fn ponder(&mut self, game: &Game) -> Option<Ponder> {
+        self.0.ponder(game)
+    }
+}
+
+/// Describes a [`Strategy`], so that fresh instances can be created for each game.
+///
+/// Written as `name[:param=value,...]`, e.g. `search:depth=3,time=500,threads=4`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StrategySpec {
+    /// The [`SimpleAi`].
+    Simple,
+    /// The [`RandomAi`].
+    Random,
+    /// The [`SearchAi`], looking up to `depth` moves ahead, in at most `time` milliseconds per
+    /// move, if given, on `threads` threads.
+    Search {
+        depth: u8,
+        time: Option<u64>,
+        threads: usize,
+    },
+    /// The [`SearchAi`], looking up to `depth` moves ahead, in at most `time` milliseconds per
+    /// move, if given, on `threads` threads, with the installed network as evaluator.
+    #[cfg(feature = "nn")]
+    Nn {
+        depth: u8,
+        time: Option<u64>,
+        threads: usize,
+    },
+}
+
+impl StrategySpec {
+    /// The strategies, as accepted by [`StrategySpec::from_str`].
+    #[cfg(not(feature = "nn"))]
+    pub const NAMES: &'static str = "simple, random, search[:depth=<2>,time=<ms>,threads=<1>]";
+    /// The strategies, as accepted by [`StrategySpec::from_str`].
+    #[cfg(feature = "nn")]
+    pub const NAMES: &'static str = "simple, random, search[:depth=<2>,time=<ms>,threads=<1>], \
+                                  nn[:depth=<2>,time=<ms>,threads=<1>]";
+
+    /// The memory used for the transposition table of a single search AI.
+    const TT_MB: usize = 16;
+
+    /// Creates a new instance of this strategy, playing as `player`, that plays from the opening
+    /// book and endgame table, if they are installed.
+    /// With `reasoning`, the strategy explains its moves, as far as it is able to.
+    pub fn build(&self, player: Player, seed: u64, reasoning: bool) -> Box<dyn Strategy> {
+        let strategy: Box<dyn Strategy> = match self {
+            Self::Simple => Box::new(SimpleAi::with_seed(player, seed).with_reasoning(reasoning)),
+            Self::Random => Box::new(RandomAi::with_seed(seed)),
+            Self::Search {
+                depth,
+                time,
+                threads,
+            } => Box::new(
+                SearchAi::new(
+                    *depth,
+                    seed,
+                    Arc::new(TranspositionTable::with_size_mb(Self::TT_MB)),
+                )
+                .with_move_time(time.map(Duration::from_millis))
+                .with_threads(*threads)
+                .with_reasoning(reasoning),
+            ),
+            #[cfg(feature = "nn")]
+            Self::Nn {
+                depth,
+                time,
+                threads,
+            } => Box::new(
+                SearchAi::new(
+                    *depth,
+                    seed,
+                    Arc::new(TranspositionTable::with_size_mb(Self::TT_MB)),
+                )
+                .with_move_time(time.map(Duration::from_millis))
+                .with_threads(*threads)
+                .with_evaluator(crate::nn::installed().expect("Parsing checks for a network"))
+                .with_reasoning(reasoning),
+            ),
+        };
+        Box::new(WithTables(strategy))
+    }
+}
+
+impl FromStr for StrategySpec {
+    type Err = ();
+
+    /// Parses a strategy by its name, and optional parameters.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let (name, params) = s.trim().split_once(':').unwrap_or((s.trim(), ""));
+        let mut params: Vec<(&str, &str)> = params
+            .split(',')
+            .filter(|param| !param.is_empty())
+            .map(|param| param.split_once('=').ok_or(()))
+            .collect::<Result<_, _>>()?;
+
+        let mut take_param = |key: &str| {
+            let i = params.iter().position(|(param, _)| *param == key)?;
+            Some(params.remove(i).1)
+        };
+
+        let ret = match name {
+            "simple" => Self::Simple,
+            "random" => Self::Random,
+            "search" => Self::Search {
+                depth: take_param("depth")
+                    .map_or(Ok(2), str::parse)
+                    .map_err(|_| ())?,
+                time: take_param("time")
+                    .map(str::parse)
+                    .transpose()
+                    .map_err(|_| ())?,
+                threads: take_param("threads")
+                    .map_or(Ok(1), str::parse)
+                    .map_err(|_| ())?,
+            },
+            // Without a network, there is nothing to evaluate with.
+            #[cfg(feature = "nn")]
+            "nn" if crate::nn::installed().is_some() => Self::Nn {
+                depth: take_param("depth")
+                    .map_or(Ok(2), str::parse)
+                    .map_err(|_| ())?,
+                time: take_param("time")
+                    .map(str::parse)
+                    .transpose()
+                    .map_err(|_| ())?,
+                threads: take_param("threads")
+                    .map_or(Ok(1), str::parse)
+                    .map_err(|_| ())?,
+            },
+            _ => return Err(()),
+        };
+        // Unknown parameters are most likely typos.
+        if params.is_empty() {
+            Ok(ret)
+        } else {
+            Err(())
+        }
+    }
+}
+
+impl Display for StrategySpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Simple => f.write_str("simple"),
+            Self::Random => f.write_str("random"),
+            Self::Search {
+                depth,
+                time,
+                threads,
+            } => {
+                write!(f, "search:depth={depth}")?;
+                if let Some(time) = time {
+                    write!(f, ",time={time}")?;
+                }
+                if *threads != 1 {
+                    write!(f, ",threads={threads}")?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "nn")]
+            Self::Nn {
+                depth,
+                time,
+                threads,
+            } => {
+                write!(f, "nn:depth={depth}")?;
+                if let Some(time) = time {
+                    write!(f, ",time={time}")?;
+                }
+                if *threads != 1 {
+                    write!(f, ",threads={threads}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::strategy::StrategySpec;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("simple".parse(), Ok(StrategySpec::Simple));
+        assert_eq!(
+            "search".parse(),
+            Ok(StrategySpec::Search {
+                depth: 2,
+                time: None,
+                threads: 1
+            })
+        );
+        let spec: StrategySpec = "search:depth=4".parse().unwrap();
+        assert_eq!(
+            spec,
+            StrategySpec::Search {
+                depth: 4,
+                time: None,
+                threads: 1
+            }
+        );
+        assert_eq!(spec.to_string().parse(), Ok(spec));
+        let spec: StrategySpec = "search:time=100,depth=6,threads=4".parse().unwrap();
+        assert_eq!(
+            spec,
+            StrategySpec::Search {
+                depth: 6,
+                time: Some(100),
+                threads: 4
+            }
+        );
+        assert_eq!(spec.to_string().parse(), Ok(spec));
+
+        assert!("search:depth=four".parse::<StrategySpec>().is_err());
+        assert!("search:width=4".parse::<StrategySpec>().is_err());
+        assert!("simple:depth=4".parse::<StrategySpec>().is_err());
+        assert!("clever".parse::<StrategySpec>().is_err());
+    }
+}
@@ -0,0 +1,333 @@
+//! Symmetries of quarto positions, used to map equivalent positions to a single representative.
+//!
+//! Two kinds of symmetries leave a position unchanged, as far as the game is concerned:
+//!  * Board symmetries: permutations of the squares that map every win line onto a win line.
+//!    On the normal 4x4 board, these are the rotations and mirrors, the swap of the inner and
+//!    outer rows and columns, and the swap of the two middle rows and columns (32 in total).
+//!    If squares win, only the rotations and mirrors remain.
+//!  * Piece symmetries: permuting the attributes of all pieces, and complementing any of them.
+
+use std::{cmp::Ordering, collections::HashSet};
+
+use crate::{
+    field::{Field, Pos},
+    piece::Piece,
+};
+
+/// A key that is equal for all positions that are equivalent under the [`Symmetry`]s.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CanonicalKey(Vec<u8>);
+
+impl CanonicalKey {
+    /// The encoded key, e.g. to store it in a file.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Restores a key from the bytes returned by [`CanonicalKey::as_bytes`].
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+/// A symmetry of the board, combined with a symmetry of the pieces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symmetry {
+    /// The target of each square, indexed by `y * size + x`.
+    squares: Vec<Pos>,
+    /// Attributes to complement, applied before permuting them.
+    flip: u8,
+    /// Attribute `i` of a piece becomes attribute `attributes[i]`.
+    attributes: Vec<usize>,
+}
+
+impl Symmetry {
+    /// Returns all symmetries that transform `field` into its canonical form.
+    /// The list is never empty, and usually contains just one element.
+    pub fn canonicalizing(field: &Field) -> Vec<Self> {
+        let size = field.size();
+        let attribute_perms = permutations(size);
+
+        let mut best: Vec<u8> = vec![];
+        let mut ret = vec![];
+
+        for squares in board_symmetries(field) {
+            let mut transformed = vec![None; size * size];
+            for (i, &(x, y)) in squares.iter().enumerate() {
+                transformed[y * size + x] = field.get((i % size, i / size));
+            }
+
+            // Mapping the first piece to `0` always yields the smallest encoding.
+            let flip = transformed
+                .iter()
+                .flatten()
+                .next()
+                .map_or(0, |piece| piece.props());
+
+            for attributes in &attribute_perms {
+                let symmetry = Self {
+                    squares: squares.clone(),
+                    flip,
+                    attributes: attributes.clone(),
+                };
+                let encoded: Vec<u8> = transformed
+                    .iter()
+                    .map(|piece| encode(piece.map(|piece| symmetry.apply_piece(piece))))
+                    .collect();
+
+                match encoded.cmp(&best) {
+                    Ordering::Less => {
+                        best = encoded;
+                        ret.clear();
+                        ret.push(symmetry);
+                    }
+                    Ordering::Equal => ret.push(symmetry),
+                    Ordering::Greater if best.is_empty() => {
+                        best = encoded;
+                        ret.push(symmetry);
+                    }
+                    Ordering::Greater => {}
+                }
+            }
+        }
+
+        ret
+    }
+
+    /// Transforms a single piece.
+    #[must_use]
+    pub fn apply_piece(&self, piece: Piece) -> Piece {
+        let props = piece.props() ^ self.flip;
+        let mut ret = 0;
+        for (i, &target) in self.attributes.iter().enumerate() {
+            ret |= ((props >> i) & 1) << target;
+        }
+        Piece::with_attributes(ret, self.attributes.len())
+    }
+
+    /// Transforms all pieces on the field, and moves them to their new squares.
+    #[must_use]
+    pub fn apply_field(&self, field: &Field) -> Field {
+        let size = field.size();
+        let mut ret = Field::with_lines(size, field.win_lines);
+        for (i, &target) in self.squares.iter().enumerate() {
+            if let Some(piece) = field.get((i % size, i / size)) {
+                ret.put(target, self.apply_piece(piece)).unwrap();
+            }
+        }
+        ret
+    }
+}
+
+/// Builds the [`CanonicalKey`] of a field, the piece to place next, and an additional `tag`,
+/// distinguishing the state of the game.
+pub fn canonical_key(tag: u8, field: &Field, next_piece: Option<Piece>) -> CanonicalKey {
+    let size = field.size();
+    let symmetries = Symmetry::canonicalizing(field);
+    let canonical = symmetries[0].apply_field(field);
+
+    let next_piece = next_piece.map(|piece| {
+        if field.empty_spaces().len() == size * size {
+            // On an empty board, all pieces are equivalent.
+            Piece::with_attributes(0, size)
+        } else {
+            symmetries
+                .iter()
+                .map(|symmetry| symmetry.apply_piece(piece))
+                .min()
+                .unwrap()
+        }
+    });
+
+    let mut key = Vec::with_capacity(size * size + 3);
+    key.push(tag);
+    // Positions on fields of different sizes are never equivalent.
+    #[allow(clippy::cast_possible_truncation)]
+    key.push(size as u8);
+    for pos in canonical.all_positions() {
+        key.push(encode(canonical.get(pos)));
+    }
+    key.push(encode(next_piece));
+    CanonicalKey(key)
+}
+
+/// Encodes a square, or the lack of a piece, in a single byte. Empty squares are the smallest.
+fn encode(piece: Option<Piece>) -> u8 {
+    piece.map_or(0, |piece| piece.props() + 1)
+}
+
+/// All permutations of the board, that keep the win lines intact.
+///
+/// Candidates permute the rows and the columns, and optionally transpose the board.
+/// The diagonals can only stay intact if the row and column permutations commute with
+/// reversing the order, so only those are tried.
+fn board_symmetries(field: &Field) -> Vec<Vec<Pos>> {
+    let sorted = |line: &mut dyn Iterator<Item = Pos>| {
+        let mut line: Vec<Pos> = line.collect();
+        line.sort_unstable();
+        line
+    };
+
+    let lines = field.lines();
+    let line_set: HashSet<Vec<Pos>> = lines
+        .iter()
+        .map(|line| sorted(&mut line.iter().copied()))
+        .collect();
+
+    let size = field.size();
+    let perms: Vec<Vec<usize>> = permutations(size)
+        .into_iter()
+        .filter(|perm| (0..size).all(|i| perm[size - 1 - i] == size - 1 - perm[i]))
+        .collect();
+
+    let mut ret = vec![];
+    for rows in &perms {
+        for cols in &perms {
+            for transpose in [false, true] {
+                let map = |(x, y): Pos| {
+                    if transpose {
+                        (rows[y], cols[x])
+                    } else {
+                        (cols[x], rows[y])
+                    }
+                };
+                if lines
+                    .iter()
+                    .all(|line| line_set.contains(&sorted(&mut line.iter().map(|&pos| map(pos)))))
+                {
+                    ret.push(
+                        (0..size * size)
+                            .map(|i| map((i % size, i / size)))
+                            .collect(),
+                    );
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// All permutations of `0..n`.
+fn permutations(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![vec![]];
+    }
+    let mut ret = vec![];
+    for perm in permutations(n - 1) {
+        for i in 0..n {
+            let mut perm = perm.clone();
+            perm.insert(i, n - 1);
+            ret.push(perm);
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        field::Field,
+        game::{Game, Player},
+        piece::{Piece, Property},
+        rules::Rules,
+        symmetry::board_symmetries,
+    };
+
+    const TEST_LIGHT_TALL: Piece = Piece::with_props(Property::Tall as u8 | Property::Light as u8);
+    const TEST_DARK_SHORT: Piece = Piece::with_props(0);
+    const TEST_DARK_TALL_ROUND: Piece =
+        Piece::with_props(Property::Tall as u8 | Property::Round as u8);
+    const TEST_DARK_TALL: Piece = Piece::with_props(Property::Tall as u8);
+
+    #[test]
+    fn test_board_symmetry_count() {
+        let mut field = Field::new();
+        assert_eq!(board_symmetries(&field).len(), 32);
+        field.win_lines.squares = true;
+        assert_eq!(board_symmetries(&field).len(), 8);
+    }
+
+    #[test]
+    fn test_rotated_field() {
+        let mut field = Field::new();
+        field.put((0, 0), TEST_LIGHT_TALL).unwrap();
+        field.put((1, 0), TEST_DARK_SHORT).unwrap();
+
+        let mut rotated = Field::new();
+        rotated.put((3, 0), TEST_LIGHT_TALL).unwrap();
+        rotated.put((3, 1), TEST_DARK_SHORT).unwrap();
+
+        assert_eq!(field.canonical(), rotated.canonical());
+    }
+
+    #[test]
+    fn test_inner_outer_swap() {
+        let mut field = Field::new();
+        field.put((0, 0), TEST_LIGHT_TALL).unwrap();
+        field.put((1, 1), TEST_DARK_SHORT).unwrap();
+
+        // Swapping the inner and outer rows and columns maps (0, 0) to (1, 1) and back.
+        let mut swapped = Field::new();
+        swapped.put((1, 1), TEST_LIGHT_TALL).unwrap();
+        swapped.put((0, 0), TEST_DARK_SHORT).unwrap();
+
+        assert_eq!(field.canonical(), swapped.canonical());
+
+        // (1, 1) is part of more squares than (0, 0), the two can't be swapped anymore.
+        let mut inner = Field::new();
+        inner.win_lines.squares = true;
+        inner.put((1, 1), TEST_LIGHT_TALL).unwrap();
+        let mut outer = Field::new();
+        outer.win_lines.squares = true;
+        outer.put((0, 0), TEST_LIGHT_TALL).unwrap();
+        assert_ne!(inner.canonical(), outer.canonical());
+    }
+
+    #[test]
+    fn test_piece_symmetries() {
+        let mut field = Field::new();
+        field.put((0, 0), TEST_LIGHT_TALL).unwrap();
+        field.put((1, 0), TEST_DARK_SHORT).unwrap();
+
+        // Complementing all properties maps these two pieces onto each other.
+        let mut complemented = Field::new();
+        complemented.put((0, 0), TEST_DARK_SHORT).unwrap();
+        complemented.put((1, 0), TEST_LIGHT_TALL).unwrap();
+
+        assert_eq!(field.canonical(), complemented.canonical());
+
+        // These two pieces only differ in one property, not in two.
+        let mut different = Field::new();
+        different.put((0, 0), TEST_DARK_TALL).unwrap();
+        different.put((1, 0), TEST_DARK_SHORT).unwrap();
+
+        assert_ne!(field.canonical(), different.canonical());
+    }
+
+    #[test]
+    fn test_canonical_key() {
+        let mut game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        game.initial_move(TEST_LIGHT_TALL).unwrap();
+
+        let mut other = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        other.initial_move(TEST_DARK_TALL_ROUND).unwrap();
+        assert_eq!(game.canonical_key(), other.canonical_key());
+
+        game.do_move((0, 0), TEST_DARK_SHORT).unwrap();
+        // The piece in hand differs from the placed one in one property, not in two.
+        other.do_move((3, 3), TEST_DARK_TALL).unwrap();
+        assert_ne!(game.canonical_key(), other.canonical_key());
+
+        let mut other = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        other.initial_move(TEST_DARK_SHORT).unwrap();
+        other.do_move((1, 1), TEST_LIGHT_TALL).unwrap();
+        // Complementing tall and light maps `other` onto `game`, and (1, 1) is equivalent to (0, 0).
+        assert_eq!(game.canonical_key(), other.canonical_key());
+
+        // Squares on a diagonal are part of three lines, the others only of two.
+        let mut check = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        check.initial_move(TEST_LIGHT_TALL).unwrap();
+        check.do_move((2, 0), TEST_DARK_SHORT).unwrap();
+        assert_ne!(game.canonical_key(), check.canonical_key());
+    }
+}
@@ -0,0 +1,228 @@
+//! Round-robin tournaments between strategies, built on [`crate::simulation`].
+
+use crate::{
+    game::{Game, Player},
+    rng::split_seed,
+    simulation::{self, Config, GameResult},
+    strategy::StrategySpec,
+};
+
+/// Wins, draws and losses of one strategy against another.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Score {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Score {
+    pub fn games(self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points, counting a draw as half a win.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn points(self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.
+    }
+
+    /// Counts the result of a game, in which we were `player`.
+    pub fn add(&mut self, result: &GameResult, player: Player) {
+        match result.winner {
+            Some(winner) if winner == player => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None => self.draws += 1,
+        }
+    }
+
+    fn flipped(self) -> Self {
+        Self {
+            wins: self.losses,
+            draws: self.draws,
+            losses: self.wins,
+        }
+    }
+}
+
+/// The results of a round-robin tournament.
+#[derive(Debug, Clone)]
+pub struct CrossTable {
+    pub strategies: Vec<StrategySpec>,
+    /// `scores[i][j]` is the score of strategy `i` against strategy `j`.
+    pub scores: Vec<Vec<Score>>,
+}
+
+/// Lets every pair of strategies play `games` games against each other, each strategy starting
+/// half of them. The seeds for each pair are derived from `seed`.
+pub fn run(
+    base_game: &Game,
+    strategies: &[StrategySpec],
+    games: usize,
+    seed: u64,
+    threads: usize,
+    reasoning: bool,
+) -> CrossTable {
+    let n = strategies.len();
+    let mut scores = vec![vec![Score::default(); n]; n];
+
+    let mut pair = 0;
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let pair_seed = split_seed(seed, pair);
+            pair += 1;
+
+            let mut score = Score::default();
+            let first = [strategies[i].clone(), strategies[j].clone()];
+            let config = Config::new(
+                base_game,
+                first,
+                split_seed(pair_seed, 0),
+                games.div_ceil(2),
+                threads,
+            );
+            for result in simulation::run(base_game, &config, reasoning) {
+                score.add(&result, Player::PlayerOne);
+            }
+            let second = [strategies[j].clone(), strategies[i].clone()];
+            let config = Config::new(
+                base_game,
+                second,
+                split_seed(pair_seed, 1),
+                games / 2,
+                threads,
+            );
+            for result in simulation::run(base_game, &config, reasoning) {
+                score.add(&result, Player::PlayerTwo);
+            }
+
+            scores[i][j] = score;
+            scores[j][i] = score.flipped();
+        }
+    }
+
+    CrossTable {
+        strategies: strategies.to_vec(),
+        scores,
+    }
+}
+
+impl CrossTable {
+    /// The total score of strategy `i` against all others.
+    pub fn total(&self, i: usize) -> Score {
+        self.scores[i]
+            .iter()
+            .fold(Score::default(), |acc, score| Score {
+                wins: acc.wins + score.wins,
+                draws: acc.draws + score.draws,
+                losses: acc.losses + score.losses,
+            })
+    }
+
+    /// Estimates the Elo rating of each strategy, with an average of 0.
+    ///
+    /// Uses the [Bradley-Terry model](https://en.wikipedia.org/wiki/Bradley%E2%80%93Terry_model),
+    /// fitted with Hunter's MM algorithm. Each pair gets one virtual draw, so that strategies that
+    /// won, or lost, all of their games still get a finite rating.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn elo(&self) -> Vec<f64> {
+        let n = self.strategies.len();
+        let mut strength = vec![1.0_f64; n];
+
+        for _ in 0..1000 {
+            let mut next: Vec<f64> = (0..n)
+                .map(|i| {
+                    let points: f64 = (0..n)
+                        .filter(|&j| j != i)
+                        .map(|j| self.scores[i][j].points() + 0.5)
+                        .sum();
+                    let denominator: f64 = (0..n)
+                        .filter(|&j| j != i)
+                        .map(|j| {
+                            (self.scores[i][j].games() + 1) as f64 / (strength[i] + strength[j])
+                        })
+                        .sum();
+                    points / denominator
+                })
+                .collect();
+            // Keep the geometric mean at 1, so the average rating stays 0.
+            let mean = next.iter().map(|s| s.ln()).sum::<f64>() / n as f64;
+            for s in &mut next {
+                *s /= mean.exp();
+            }
+            strength = next;
+        }
+
+        strength.iter().map(|s| 400. * s.log10()).collect()
+    }
+
+    /// Prints the cross table with the score of each row against each column, and the ratings.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn pp(&self) {
+        let names: Vec<String> = self.strategies.iter().map(ToString::to_string).collect();
+        let width = names.iter().map(String::len).max().unwrap_or(0).max(8);
+
+        print!("{:width$}", "");
+        for name in &names {
+            print!("  {name:>width$}");
+        }
+        println!("  {:>8}  {:>6}", "Score", "Elo");
+
+        let elo = self.elo();
+        for (i, name) in names.iter().enumerate() {
+            print!("{name:width$}");
+            for j in 0..names.len() {
+                let score = self.scores[i][j];
+                if i == j || score.games() == 0 {
+                    print!("  {:>width$}", "-");
+                } else {
+                    let rate = format!("{:.1}%", score.points() / score.games() as f64 * 100.);
+                    print!("  {rate:>width$}");
+                }
+            }
+            let total = self.total(i);
+            let rate = format!("{:.1}%", total.points() / total.games() as f64 * 100.);
+            println!("  {rate:>8}  {:>+6.0}", elo[i]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        strategy::StrategySpec,
+        tournament::{CrossTable, Score},
+    };
+
+    #[test]
+    fn test_elo() {
+        let even = Score {
+            wins: 5,
+            draws: 0,
+            losses: 5,
+        };
+        let strong = Score {
+            wins: 9,
+            draws: 0,
+            losses: 1,
+        };
+        let table = CrossTable {
+            strategies: vec![StrategySpec::Simple, StrategySpec::Random],
+            scores: vec![
+                vec![Score::default(), strong],
+                vec![strong.flipped(), Score::default()],
+            ],
+        };
+        let elo = table.elo();
+        assert!(elo[0] > 100.);
+        assert!((elo[0] + elo[1]).abs() < 0.001);
+
+        let table = CrossTable {
+            strategies: vec![StrategySpec::Simple, StrategySpec::Random],
+            scores: vec![
+                vec![Score::default(), even],
+                vec![even.flipped(), Score::default()],
+            ],
+        };
+        assert!(table.elo()[0].abs() < 0.001);
+    }
+}
@@ -0,0 +1,253 @@
+//! A fixed-size transposition table, shared by search-based AIs.
+//!
+//! Entries are stored lock-free, as two atomics each: the data, and the hash xor-ed with the data.
+//! A torn write from another thread then simply looks like a miss, which makes the table safe to
+//! use from many threads at once, e.g. for a parallel search.
+
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::{
+    field::{Field, Pos},
+    piece::Piece,
+};
+
+/// What the stored score tells us about the real score of a position.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact.
+    Exact,
+    /// The real score is at least the stored score.
+    Lower,
+    /// The real score is at most the stored score.
+    Upper,
+}
+
+/// A search result for one position.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub score: i16,
+    /// The remaining search depth when this entry was stored.
+    pub depth: u8,
+    pub bound: Bound,
+    /// Where to put the piece in hand for the best move, `None` for the initial move.
+    pub pos: Option<Pos>,
+    /// The piece to give to the opponent for the best move, `None` if the game ended.
+    pub piece: Option<Piece>,
+}
+
+const NONE: u64 = 0xff;
+/// Set for all stored entries, so that they are never all zero, like an empty slot.
+const VALID: u64 = 1 << 63;
+
+impl Entry {
+    fn pack(self, generation: u8) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        let pos = self
+            .pos
+            .map_or(NONE, |(x, y)| (y * Field::MAX_SIZE + x) as u64);
+        let piece = self.piece.map_or(NONE, |piece| u64::from(piece.props()));
+        let attributes = self.piece.map_or(0, |piece| piece.attributes() as u64);
+
+        let score = u64::from(self.score.cast_unsigned());
+        score
+            | u64::from(self.depth) << 16
+            | bound << 24
+            | pos << 32
+            | piece << 40
+            | u64::from(generation) << 48
+            | attributes << 56
+            | VALID
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn unpack(data: u64) -> Self {
+        let bound = match (data >> 24) & 0xff {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        };
+        let pos = (data >> 32) & 0xff;
+        let piece = (data >> 40) & 0xff;
+        let attributes = (data >> 56) & 0x7f;
+        Self {
+            score: (data as u16).cast_signed(),
+            depth: (data >> 16) as u8,
+            bound,
+            pos: (pos != NONE).then_some((
+                pos as usize % Field::MAX_SIZE,
+                pos as usize / Field::MAX_SIZE,
+            )),
+            piece: (piece != NONE)
+                .then(|| Piece::with_attributes(piece as u8, attributes as usize)),
+        }
+    }
+}
+
+fn generation_of(data: u64) -> u8 {
+    #[allow(clippy::cast_possible_truncation)]
+    let generation = (data >> 48) as u8;
+    generation
+}
+
+/// One slot of the table.
+#[derive(Debug, Default)]
+struct Slot {
+    /// The hash of the position, xor-ed with `data`.
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn load(&self) -> (u64, u64) {
+        let data = self.data.load(Ordering::Relaxed);
+        (self.check.load(Ordering::Relaxed) ^ data, data)
+    }
+
+    fn store(&self, hash: u64, data: u64) {
+        self.check.store(hash ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+/// A transposition table with a fixed number of buckets.
+///
+/// Each bucket has two slots: the first one keeps the deepest result of the current search,
+/// the second one is always replaced.
+#[derive(Debug)]
+pub struct TranspositionTable {
+    buckets: Vec<[Slot; 2]>,
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    /// Creates a table that uses about `megabytes` of memory.
+    pub fn with_size_mb(megabytes: usize) -> Self {
+        let bucket_size = size_of::<[Slot; 2]>();
+        let buckets = (megabytes * 1024 * 1024 / bucket_size).max(1);
+        // A power of two lets us pick the bucket with a mask.
+        let buckets = if buckets.is_power_of_two() {
+            buckets
+        } else {
+            buckets.next_power_of_two() / 2
+        };
+        Self {
+            buckets: (0..buckets).map(|_| Default::default()).collect(),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    fn bucket(&self, hash: u64) -> &[Slot; 2] {
+        #[allow(clippy::cast_possible_truncation)]
+        &self.buckets[hash as usize & (self.buckets.len() - 1)]
+    }
+
+    /// Marks all stored entries as stale, so that the next search may replace them.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Looks up the entry for the position with the given `hash`.
+    pub fn probe(&self, hash: u64) -> Option<Entry> {
+        self.bucket(hash)
+            .iter()
+            .map(Slot::load)
+            .find(|&(slot_hash, data)| slot_hash == hash && data != 0)
+            .map(|(_, data)| Entry::unpack(data))
+    }
+
+    /// Stores the entry for the position with the given `hash`.
+    pub fn store(&self, hash: u64, entry: Entry) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let data = entry.pack(generation);
+        let [deepest, always] = self.bucket(hash);
+
+        let (deepest_hash, deepest_data) = deepest.load();
+        if deepest_data == 0
+            || deepest_hash == hash
+            || generation_of(deepest_data) != generation
+            || Entry::unpack(deepest_data).depth <= entry.depth
+        {
+            if deepest_hash != hash && deepest_data != 0 {
+                // Keep the replaced entry around for a while.
+                always.store(deepest_hash, deepest_data);
+            }
+            deepest.store(hash, data);
+        } else {
+            always.store(hash, data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::{
+        piece::Piece,
+        tt::{Bound, Entry, TranspositionTable},
+    };
+
+    const ENTRY: Entry = Entry {
+        score: -3,
+        depth: 4,
+        bound: Bound::Lower,
+        pos: Some((2, 3)),
+        piece: Some(Piece::with_props(0b1010)),
+    };
+
+    #[test]
+    fn test_store_probe() {
+        let tt = TranspositionTable::with_size_mb(1);
+        assert_eq!(tt.probe(1337), None);
+        tt.store(1337, ENTRY);
+        assert_eq!(tt.probe(1337), Some(ENTRY));
+
+        let initial = Entry { pos: None, ..ENTRY };
+        tt.store(42, initial);
+        assert_eq!(tt.probe(42), Some(initial));
+    }
+
+    #[test]
+    fn test_replacement() {
+        let tt = TranspositionTable::with_size_mb(1);
+        let other_hash = 1337 + tt.buckets.len() as u64;
+        let shallow = Entry { depth: 1, ..ENTRY };
+        let third_hash = other_hash + tt.buckets.len() as u64;
+
+        tt.store(1337, ENTRY);
+        tt.store(other_hash, shallow);
+        tt.store(third_hash, shallow);
+        // The deeper entry survives, the always-replace slot was overwritten.
+        assert_eq!(tt.probe(1337), Some(ENTRY));
+        assert_eq!(tt.probe(other_hash), None);
+        assert_eq!(tt.probe(third_hash), Some(shallow));
+
+        // In a new search, old entries make room.
+        tt.new_search();
+        tt.store(other_hash, shallow);
+        assert_eq!(tt.probe(other_hash), Some(shallow));
+        assert_eq!(tt.probe(1337), Some(ENTRY));
+    }
+
+    #[test]
+    fn test_threads() {
+        let tt = TranspositionTable::with_size_mb(1);
+        thread::scope(|s| {
+            for t in 0..4_u64 {
+                let tt = &tt;
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        tt.store(t * 1000 + i, ENTRY);
+                    }
+                });
+            }
+        });
+        for hash in 0..4000 {
+            assert_eq!(tt.probe(hash), Some(ENTRY));
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Tunes the [`Weights`] of the evaluation by self-play, with
+//! [SPSA](https://en.wikipedia.org/wiki/Simultaneous_perturbation_stochastic_approximation).
+//!
+//! Each iteration perturbs all weights at once in a random direction, plays a match between
+//! [`SearchAi`]s with the weights moved forth and back along it, and moves the weights towards
+//! the side that scored better. All randomness derives from a single seed, so a tuning run only
+//! depends on the seed, not on the number of threads.
+
+use std::sync::Arc;
+
+use crate::{
+    eval::{WeightedEvaluator, Weights},
+    game::{Game, Player},
+    piece::Piece,
+    rng::RomuDuoJrRand,
+    search::SearchAi,
+    simulation::{self, GameResult},
+    strategy::Strategy,
+    tt::TranspositionTable,
+};
+
+/// The parameters of a tuning run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spsa {
+    pub iterations: usize,
+    /// The number of games per iteration, each side plays half of them as player one.
+    pub games: usize,
+    /// How many moves deep the [`SearchAi`]s search.
+    pub depth: u8,
+    /// The step size of the first iteration, relative to the score difference of the match.
+    pub a: f64,
+    /// The perturbation of the first iteration.
+    pub c: f64,
+}
+
+impl Spsa {
+    /// The memory used for the transposition table of each search AI.
+    const TT_MB: usize = 1;
+
+    /// Tunes `weights` for games starting at `base_game`, on `threads` threads.
+    /// Calls `report` after each iteration with the iteration, the score of the weights moved
+    /// forth in the match, and the new weights.
+    pub fn tune(
+        &self,
+        base_game: &Game,
+        weights: Weights,
+        seed: u64,
+        threads: usize,
+        mut report: impl FnMut(usize, f64, &Weights),
+    ) -> Weights {
+        let attributes = base_game.field.size();
+        let mut rng = RomuDuoJrRand::with_seed(seed);
+        let mut theta = params(&weights, attributes);
+
+        // The usual decay of the gains, see Spall, "Implementation of the Simultaneous
+        // Perturbation Algorithm for Stochastic Optimization".
+        #[allow(clippy::cast_precision_loss)]
+        let stability = self.iterations as f64 / 10.;
+        for iteration in 0..self.iterations {
+            #[allow(clippy::cast_precision_loss)]
+            let k = iteration as f64 + 1.;
+            let a = self.a / (k + stability).powf(0.602);
+            let c = self.c / k.powf(0.101);
+
+            let delta: Vec<f64> = theta
+                .iter()
+                .map(|_| if rng.next() & 1 == 0 { -1. } else { 1. })
+                .collect();
+            let shifted = |sign: f64| {
+                let params: Vec<f64> = theta
+                    .iter()
+                    .zip(&delta)
+                    .map(|(theta, delta)| theta + sign * c * delta)
+                    .collect();
+                with_params(&params, attributes)
+            };
+            let score =
+                self.play_match(base_game, [shifted(1.), shifted(-1.)], rng.next(), threads);
+
+            for (theta, delta) in theta.iter_mut().zip(&delta) {
+                *theta += a * (2. * score - 1.) / (2. * c) * delta;
+            }
+            report(iteration + 1, score, &with_params(&theta, attributes));
+        }
+        with_params(&theta, attributes)
+    }
+
+    /// Plays a match between search AIs with the `weights` of each side, and returns the share
+    /// of points the first side scored.
+    fn play_match(
+        &self,
+        base_game: &Game,
+        weights: [Weights; 2],
+        seed: u64,
+        threads: usize,
+    ) -> f64 {
+        let results = simulation::run_with(seed, self.games, threads, |i, game_seed| {
+            let mut rng = RomuDuoJrRand::with_seed(game_seed);
+            let ais = [i % 2, (i + 1) % 2].map(|side| -> Box<dyn Strategy> {
+                Box::new(
+                    SearchAi::new(
+                        self.depth,
+                        rng.next(),
+                        Arc::new(TranspositionTable::with_size_mb(Self::TT_MB)),
+                    )
+                    .with_evaluator(Arc::new(WeightedEvaluator::new(weights[side]))),
+                )
+            });
+            simulation::play_game_with(base_game, ais, game_seed, false)
+        });
+        let points: f64 = results
+            .iter()
+            .enumerate()
+            .map(|(i, result)| first_points(i, result))
+            .sum();
+        #[allow(clippy::cast_precision_loss)]
+        let games = results.len().max(1) as f64;
+        points / games
+    }
+}
+
+/// The points of the first side in game `i`, in which it played player one if `i` is even.
+fn first_points(i: usize, result: &GameResult) -> f64 {
+    let first = if i.is_multiple_of(2) {
+        Player::PlayerOne
+    } else {
+        Player::PlayerTwo
+    };
+    match result.winner {
+        Some(winner) if winner == first => 1.,
+        Some(_) => 0.,
+        None => 0.5,
+    }
+}
+
+/// The weights that apply to a field with `attributes` attributes, as parameters to tune.
+fn params(weights: &Weights, attributes: usize) -> Vec<f64> {
+    weights.shared[..attributes]
+        .iter()
+        .chain([&weights.safe_pieces, &weights.odd_safe, &weights.threat])
+        .map(|&weight| f64::from(weight))
+        .collect()
+}
+
+/// The inverse of [`params`], rounding each parameter to the nearest weight.
+fn with_params(params: &[f64], attributes: usize) -> Weights {
+    #[allow(clippy::cast_possible_truncation)]
+    let weight = |param: f64| {
+        param
+            .round()
+            .clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16
+    };
+    let mut shared = [0; Piece::MAX_ATTRIBUTES];
+    for (shared, &param) in shared.iter_mut().zip(&params[..attributes]) {
+        *shared = weight(param);
+    }
+    Weights {
+        shared,
+        safe_pieces: weight(params[attributes]),
+        odd_safe: weight(params[attributes + 1]),
+        threat: weight(params[attributes + 2]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        eval::Weights,
+        game::{Game, Player},
+        rules::Rules,
+        tune::{params, with_params, Spsa},
+    };
+
+    #[test]
+    fn test_params() {
+        let weights: Weights = "shared = 1 2 3 4\nsafe_pieces = 5\nodd_safe = -6\nthreat = 7"
+            .parse()
+            .unwrap();
+        assert_eq!(params(&weights, 4), [1., 2., 3., 4., 5., -6., 7.]);
+        assert_eq!(with_params(&params(&weights, 4), 4), weights);
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let spsa = Spsa {
+            iterations: 2,
+            games: 4,
+            depth: 1,
+            a: 20.,
+            c: 2.,
+        };
+        let game = Game::new(Player::PlayerOne, Rules::CLASSIC);
+        let mut scores = vec![];
+        let tuned = spsa.tune(&game, Weights::default(), 7, 1, |_, score, _| {
+            scores.push(score);
+        });
+        assert_eq!(scores.len(), 2);
+        assert_eq!(
+            spsa.tune(&game, Weights::default(), 7, 3, |_, _, _| {}),
+            tuned
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Verdicts on the moves of human players, backed by a [`SearchAi`] that compares each move to
+//! the best one in the position before it.
+
+use core::fmt::Display;
+use std::sync::Arc;
+
+use crate::{
+    game::{Game, Move, Player},
+    search::{SearchAi, WIN, WIN_THRESHOLD},
+    tt::TranspositionTable,
+};
+
+/// How good a move was, compared to the best move.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// No other move is known to lead to a better outcome.
+    Best,
+    /// The game is still undecided, but the best move scores clearly better.
+    Inaccuracy,
+    /// The move wins slower than the best move.
+    SlowerWin,
+    /// The move loses, but the best move loses later.
+    FasterLoss,
+    /// The move misses a win, but does not lose.
+    MissedWin,
+    /// The move hands the opponent a win on their next move.
+    GaveWinningPiece,
+    /// The move loses, the best move did not.
+    Loses,
+}
+
+impl Verdict {
+    /// How much worse than the best move an undecided move has to score to be an inaccuracy.
+    /// Smaller differences are within the noise of the evaluation.
+    pub const INACCURACY: i16 = 10;
+
+    /// Judges a move that scores `score`, when the best move scores `best`, both from the view of
+    /// the player that moves, see [`SearchAi::evaluate_move`].
+    pub fn new(score: i16, best: i16) -> Self {
+        let undecided = |score: i16| score.abs() <= WIN_THRESHOLD;
+        if score >= best {
+            Self::Best
+        } else if undecided(score) && undecided(best) {
+            if best - score >= Self::INACCURACY {
+                Self::Inaccuracy
+            } else {
+                Self::Best
+            }
+        } else if score > WIN_THRESHOLD {
+            Self::SlowerWin
+        } else if best < -WIN_THRESHOLD {
+            Self::FasterLoss
+        } else if score >= -WIN_THRESHOLD {
+            Self::MissedWin
+        } else if score == -(WIN - 2) {
+            // The opponent wins on their move right after this one.
+            Self::GaveWinningPiece
+        } else {
+            Self::Loses
+        }
+    }
+
+    /// Returns true for blunders, that turn the outcome of the game.
+    pub fn is_blunder(self) -> bool {
+        matches!(self, Self::MissedWin | Self::GaveWinningPiece | Self::Loses)
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Best => "best move",
+            Self::Inaccuracy => "inaccuracy: a better move existed",
+            Self::SlowerWin => "inaccuracy: a faster win existed",
+            Self::FasterLoss => "inaccuracy: the loss could have been delayed",
+            Self::MissedWin => "blunder: missed a win",
+            Self::GaveWinningPiece => "blunder: gave a winning piece",
+            Self::Loses => "blunder: this loses",
+        })
+    }
+}
+
+/// Judges moves, and remembers the verdicts for a summary.
+pub struct Tutor {
+    search: SearchAi,
+    /// The round, player and verdict of each judged move.
+    verdicts: Vec<(u8, Player, Verdict)>,
+}
+
+impl Tutor {
+    /// The memory used for the transposition table.
+    const TT_MB: usize = 16;
+
+    /// Creates a tutor that searches `depth` moves deep.
+    pub fn new(depth: u8, seed: u64) -> Self {
+        Self {
+            search: SearchAi::new(
+                depth,
+                seed,
+                Arc::new(TranspositionTable::with_size_mb(Self::TT_MB)),
+            ),
+            verdicts: vec![],
+        }
+    }
+
+    /// Judges `mv`, played in `game`, and remembers the verdict.
+    /// Returns `None` for the initial move, where every piece is as good as any other.
+    pub fn judge(&mut self, game: &Game, mv: Move) -> Option<Verdict> {
+        if game.is_initial_move() {
+            return None;
+        }
+        // The tutor takes its time, whatever the clock says.
+        let mut game = game.clone();
+        game.clock = None;
+
+        let (_, best) = self.search.best_move(&game)?;
+        let score = self.search.evaluate_move(&game, mv);
+        let verdict = Verdict::new(score, best);
+        self.verdicts.push((game.round(), game.player(), verdict));
+        Some(verdict)
+    }
+
+    /// Prints how many moves were best, inaccurate or blunders, and lists the mistakes.
+    pub fn pp_summary(&self) {
+        let count = |f: fn(&Verdict) -> bool| self.verdicts.iter().filter(|(_, _, v)| f(v)).count();
+        println!("Tutor summary:");
+        println!(
+            "{} moves, {} best, {} inaccuracies, {} blunders",
+            self.verdicts.len(),
+            count(|v| *v == Verdict::Best),
+            count(|v| *v != Verdict::Best && !v.is_blunder()),
+            count(|v| v.is_blunder())
+        );
+        for (round, player, verdict) in &self.verdicts {
+            if *verdict != Verdict::Best {
+                println!("  Round {round}, {player}: {verdict}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        game::{Move, Player},
+        notation::parse_notation,
+        piece::Piece,
+        search::WIN,
+        tutor::{Tutor, Verdict},
+    };
+
+    #[test]
+    fn test_verdicts() {
+        assert_eq!(Verdict::new(5, 5), Verdict::Best);
+        assert_eq!(Verdict::new(0, Verdict::INACCURACY - 1), Verdict::Best);
+        assert_eq!(Verdict::new(0, Verdict::INACCURACY), Verdict::Inaccuracy);
+        assert_eq!(Verdict::new(WIN - 5, WIN - 3), Verdict::SlowerWin);
+        assert_eq!(Verdict::new(-(WIN - 2), -(WIN - 4)), Verdict::FasterLoss);
+        assert_eq!(Verdict::new(0, WIN - 3), Verdict::MissedWin);
+        assert_eq!(Verdict::new(-(WIN - 2), 0), Verdict::GaveWinningPiece);
+        assert_eq!(Verdict::new(-(WIN - 4), 0), Verdict::Loses);
+        assert!(!Verdict::Inaccuracy.is_blunder());
+        assert!(Verdict::Loses.is_blunder());
+    }
+
+    #[test]
+    fn test_judge() {
+        // 3 completes the top row.
+        let game = parse_notation("012./..../..../.... 3 1").unwrap();
+        let mut tutor = Tutor::new(2, 1);
+        let win = Move::Place {
+            pos: (3, 0),
+            next_piece: Piece::with_props(4),
+        };
+        assert_eq!(tutor.judge(&game, win), Some(Verdict::Best));
+        let miss = Move::Place {
+            pos: (3, 3),
+            next_piece: Piece::with_props(15),
+        };
+        assert_eq!(tutor.judge(&game, miss), Some(Verdict::MissedWin));
+
+        // Pieces with property 8, or without property 1, complete the top row.
+        let game = parse_notation("e8c./..../..../.... 3 2").unwrap();
+        let gift = Move::Place {
+            pos: (3, 3),
+            next_piece: Piece::with_props(0),
+        };
+        assert_eq!(tutor.judge(&game, gift), Some(Verdict::GaveWinningPiece));
+        let block = Move::Place {
+            pos: (3, 0),
+            next_piece: Piece::with_props(1),
+        };
+        assert_eq!(tutor.judge(&game, block), Some(Verdict::Best));
+        assert_eq!(
+            tutor
+                .verdicts
+                .iter()
+                .map(|(_, player, _)| *player)
+                .collect::<Vec<_>>(),
+            [
+                Player::PlayerOne,
+                Player::PlayerOne,
+                Player::PlayerTwo,
+                Player::PlayerTwo
+            ]
+        );
+    }
+}
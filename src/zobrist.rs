@@ -0,0 +1,64 @@
+//! [Zobrist hashing](https://en.wikipedia.org/wiki/Zobrist_hashing) for quarto positions.
+//!
+//! Every piece on every square, and every piece that is about to be placed, gets a random key.
+//! The hash of a position is the xor of all its keys, so it can be updated incrementally.
+
+use crate::{
+    field::{Field, Pos},
+    piece::Piece,
+};
+
+// Fields of all sizes share the keys, with the largest possible field and pieces.
+const PIECES: usize = 1 << Piece::MAX_ATTRIBUTES;
+const SQUARES: usize = Field::MAX_SIZE * Field::MAX_SIZE;
+
+/// Keys for each piece on each square, indexed by `[y * Field::MAX_SIZE + x][piece]`.
+static PLACED: [[u64; PIECES]; SQUARES] = {
+    let mut keys = [[0; PIECES]; SQUARES];
+    let mut state = 0x5175_6172_746f_5f72;
+    let mut square = 0;
+    while square < SQUARES {
+        let mut piece = 0;
+        while piece < PIECES {
+            state = splitmix64(state);
+            keys[square][piece] = state;
+            piece += 1;
+        }
+        square += 1;
+    }
+    keys
+};
+
+/// Keys for the piece that the next player has to place.
+static IN_HAND: [u64; PIECES] = {
+    let mut keys = [0; PIECES];
+    let mut state = 0x6861_6e64_5f6b_6579;
+    let mut piece = 0;
+    while piece < PIECES {
+        state = splitmix64(state);
+        keys[piece] = state;
+        piece += 1;
+    }
+    keys
+};
+
+/// The key for positions in which a missed win can be claimed, see [`crate::rules::Rules::call_quarto`].
+pub const CLAIMABLE: u64 = splitmix64(0x636c_6169_6d61_626c);
+
+/// A fixed pseudo random sequence, so hashes are the same for each run.
+const fn splitmix64(state: u64) -> u64 {
+    let mut z = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The key to xor in or out when `piece` is put on, or removed from, `pos`.
+pub fn placed(pos: Pos, piece: Piece) -> u64 {
+    PLACED[pos.1 * Field::MAX_SIZE + pos.0][piece.props() as usize]
+}
+
+/// The key of the piece that is given to the next player.
+pub fn in_hand(piece: Piece) -> u64 {
+    IN_HAND[piece.props() as usize]
+}